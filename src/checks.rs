@@ -0,0 +1,156 @@
+use crate::playbook::PlaybookLine;
+use regex::Regex;
+
+// Captured output beyond this is cut, keeping its end
+const MAX_OUTPUT_LEN: usize = 64 * 1024;
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum CheckStatus {
+    Running,
+    Passed,
+    Failed(String),
+}
+
+// Outcome of a step run in the background, against its `@expect` and `@expect-exit` annotations
+#[derive(Debug, Clone)]
+pub(crate) struct CheckResult {
+    pub status: CheckStatus,
+    pub exit_code: Option<i32>,
+    // Stdout followed by stderr
+    pub output: String,
+}
+
+impl CheckResult {
+    pub(crate) fn running() -> Self {
+        Self {
+            status: CheckStatus::Running,
+            exit_code: None,
+            output: String::new(),
+        }
+    }
+
+    pub(crate) fn label(&self) -> String {
+        match &self.status {
+            CheckStatus::Running => "running".to_string(),
+            CheckStatus::Passed => "PASS".to_string(),
+            CheckStatus::Failed(_) => "FAIL".to_string(),
+        }
+    }
+
+    // A line describing the outcome, such as `FAIL: exit code 1, expected 0`
+    pub(crate) fn summary(&self) -> String {
+        let exit_code = self
+            .exit_code
+            .map(|c| c.to_string())
+            .unwrap_or("none".to_string());
+        match &self.status {
+            CheckStatus::Running => "running...".to_string(),
+            CheckStatus::Passed => format!("PASS, exit code {}", exit_code),
+            CheckStatus::Failed(reason) => format!("FAIL: {}", reason),
+        }
+    }
+}
+
+// The exit code must be the expected one, 0 by default, and the output must match the pattern if any
+pub(crate) fn evaluate(
+    line: &PlaybookLine,
+    exit_code: Option<i32>,
+    stdout: &[u8],
+    stderr: &[u8],
+) -> CheckResult {
+    let mut output = String::from_utf8_lossy(stdout).to_string();
+    output.push_str(&String::from_utf8_lossy(stderr));
+    if output.len() > MAX_OUTPUT_LEN {
+        let mut begin = output.len() - MAX_OUTPUT_LEN;
+        while !output.is_char_boundary(begin) {
+            begin += 1;
+        }
+        output = output[begin..].to_string();
+    }
+
+    let expected_exit = line.expect_exit.unwrap_or(0);
+    let failure = if exit_code != Some(expected_exit) {
+        Some(match exit_code {
+            Some(code) => format!("exit code {}, expected {}", code, expected_exit),
+            None => format!("no exit code, expected {}", expected_exit),
+        })
+    } else {
+        match line.expect.as_deref().map(Regex::new) {
+            Some(Ok(pattern)) if !pattern.is_match(&output) => {
+                Some(format!("output doesn't match '{}'", pattern))
+            }
+            Some(Err(e)) => Some(format!("invalid pattern: {}", e)),
+            _ => None,
+        }
+    };
+
+    CheckResult {
+        status: failure.map_or(CheckStatus::Passed, CheckStatus::Failed),
+        exit_code,
+        output,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn step(expect: Option<&str>, expect_exit: Option<i32>) -> PlaybookLine {
+        PlaybookLine {
+            content: "make test".to_string(),
+            expect: expect.map(str::to_string),
+            expect_exit,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn exit_code_zero_passes_by_default() {
+        let result = evaluate(&step(None, None), Some(0), b"ok\n", b"");
+        assert_eq!(result.status, CheckStatus::Passed);
+        assert_eq!(result.summary(), "PASS, exit code 0");
+
+        let result = evaluate(&step(None, None), Some(2), b"", b"");
+        assert_eq!(
+            result.status,
+            CheckStatus::Failed("exit code 2, expected 0".to_string())
+        );
+        assert_eq!(result.label(), "FAIL");
+    }
+
+    #[test]
+    fn expected_exit_code_is_checked() {
+        let result = evaluate(&step(None, Some(1)), Some(1), b"", b"");
+        assert_eq!(result.status, CheckStatus::Passed);
+
+        let result = evaluate(&step(None, Some(1)), None, b"", b"");
+        assert_eq!(result.summary(), "FAIL: no exit code, expected 1");
+    }
+
+    #[test]
+    fn output_must_match_the_pattern() {
+        let line = step(Some(r"\d+ passed"), None);
+        let result = evaluate(&line, Some(0), b"running\n", b"12 passed\n");
+        assert_eq!(result.status, CheckStatus::Passed);
+        assert_eq!(result.output, "running\n12 passed\n");
+
+        let result = evaluate(&line, Some(0), b"0 tests\n", b"");
+        assert_eq!(
+            result.status,
+            CheckStatus::Failed(r"output doesn't match '\d+ passed'".to_string())
+        );
+
+        let result = evaluate(&step(Some("("), None), Some(0), b"", b"");
+        assert!(result.summary().starts_with("FAIL: invalid pattern"));
+    }
+
+    #[test]
+    fn long_output_keeps_its_end() {
+        let mut stdout = "é".repeat(MAX_OUTPUT_LEN);
+        stdout.push_str("done");
+        let result = evaluate(&step(Some("done$"), None), Some(0), stdout.as_bytes(), b"");
+        assert!(result.output.len() <= MAX_OUTPUT_LEN);
+        assert!(result.output.ends_with("édone"));
+        assert_eq!(result.status, CheckStatus::Passed);
+    }
+}
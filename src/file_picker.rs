@@ -1,22 +1,41 @@
+use crate::front_matter;
 use crate::tab_manager::TabManager;
 use std::fs;
 use std::path::PathBuf;
 
+// Name of the entry listing the shell history instead of a file
+const SHELL_HISTORY_NAME: &str = "[shell history]";
+
 #[derive(Default, Debug, Clone)]
 pub(crate) struct FileItem {
     pub id: usize,
     pub name: String,
+    // Title declared in the front matter of the file
+    pub title: Option<String>,
+    pub is_shell_history: bool,
 }
 
 impl FileItem {
     fn new(id: usize, name: String) -> Self {
-        Self { id, name }
+        Self {
+            id,
+            name,
+            title: None,
+            is_shell_history: false,
+        }
+    }
+
+    // The title, if the file declares one, or else the file name
+    pub(crate) fn label(&self) -> &str {
+        self.title.as_deref().unwrap_or(&self.name)
     }
 }
 
 #[derive(Default, Debug, Clone)]
 pub(crate) struct FilePicker {
     items: Vec<FileItem>,
+    // Lists the shell history after the files
+    pub shell_history: bool,
 }
 
 impl FilePicker {
@@ -57,9 +76,16 @@ impl FilePicker {
 
         let mut items = Vec::new();
         for (i, file) in files.iter().enumerate() {
-            let item = FileItem::new(i + 1, file.clone());
+            let mut item = FileItem::new(i + 1, file.clone());
+            item.title = front_matter::read_title(&cwd.join(file));
             items.push(item);
         }
+        if self.shell_history {
+            items.push(FileItem {
+                is_shell_history: true,
+                ..FileItem::new(items.len() + 1, SHELL_HISTORY_NAME.to_string())
+            });
+        }
 
         self.items = items;
 
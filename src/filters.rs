@@ -1,4 +1,7 @@
-use crate::{file_picker::FileItem, PlaybookLine};
+use crate::{
+    file_picker::FileItem, history::HistoryEntry, panes::PaneItem, playbook::PlaybookLine,
+};
+use std::collections::BTreeSet;
 use std::fmt;
 use std::fmt::Formatter;
 
@@ -36,11 +39,17 @@ impl Mode {
 pub(crate) struct PlaybookFilter {
     mode: Mode,
     filter: String,
+    // Steps whose sub-steps are listed
+    expanded: BTreeSet<usize>,
 }
 
 impl PlaybookFilter {
-    pub(crate) fn new(mode: Mode, filter: String) -> Self {
-        PlaybookFilter { mode, filter }
+    pub(crate) fn new(mode: Mode, filter: String, expanded: BTreeSet<usize>) -> Self {
+        PlaybookFilter {
+            mode,
+            filter,
+            expanded,
+        }
     }
 
     fn keep_by_name(&self, line: &PlaybookLine) -> bool {
@@ -48,6 +57,8 @@ impl PlaybookFilter {
             return true;
         }
         line.content.contains(&self.filter)
+            || line.name.as_ref().is_some_and(|n| n.contains(&self.filter))
+            || line.tags.iter().any(|t| t.contains(&self.filter))
     }
 
     fn keep_by_id(&self, line: &PlaybookLine) -> bool {
@@ -57,6 +68,9 @@ impl PlaybookFilter {
 
 impl Filter<PlaybookLine> for PlaybookFilter {
     fn keep(&self, line: &PlaybookLine) -> bool {
+        if line.parent.is_some_and(|p| !self.expanded.contains(&p)) {
+            return false;
+        }
         match self.mode {
             Mode::ID => self.keep_by_id(line),
             _ => self.keep_by_name(line),
@@ -79,6 +93,10 @@ impl FileFilter {
             return true;
         }
         file.name.contains(&self.filter)
+            || file
+                .title
+                .as_ref()
+                .is_some_and(|t| t.contains(&self.filter))
     }
 
     fn keep_by_id(&self, file: &FileItem) -> bool {
@@ -94,3 +112,65 @@ impl Filter<FileItem> for FileFilter {
         }
     }
 }
+
+pub(crate) struct PaneFilter {
+    mode: Mode,
+    filter: String,
+}
+
+impl PaneFilter {
+    pub(crate) fn new(mode: Mode, filter: String) -> Self {
+        PaneFilter { mode, filter }
+    }
+
+    fn keep_by_name(&self, pane: &PaneItem) -> bool {
+        if self.filter.is_empty() {
+            return true;
+        }
+        pane.label.contains(&self.filter)
+    }
+
+    fn keep_by_id(&self, pane: &PaneItem) -> bool {
+        pane.id.to_string().starts_with(&self.filter.to_string())
+    }
+}
+
+impl Filter<PaneItem> for PaneFilter {
+    fn keep(&self, pane: &PaneItem) -> bool {
+        match self.mode {
+            Mode::ID => self.keep_by_id(pane),
+            _ => self.keep_by_name(pane),
+        }
+    }
+}
+
+pub(crate) struct HistoryFilter {
+    mode: Mode,
+    filter: String,
+}
+
+impl HistoryFilter {
+    pub(crate) fn new(mode: Mode, filter: String) -> Self {
+        HistoryFilter { mode, filter }
+    }
+
+    fn keep_by_name(&self, entry: &HistoryEntry) -> bool {
+        if self.filter.is_empty() {
+            return true;
+        }
+        entry.label().contains(&self.filter)
+    }
+
+    fn keep_by_id(&self, entry: &HistoryEntry) -> bool {
+        entry.id.to_string().starts_with(&self.filter.to_string())
+    }
+}
+
+impl Filter<HistoryEntry> for HistoryFilter {
+    fn keep(&self, entry: &HistoryEntry) -> bool {
+        match self.mode {
+            Mode::ID => self.keep_by_id(entry),
+            _ => self.keep_by_name(entry),
+        }
+    }
+}
@@ -0,0 +1,190 @@
+use crate::playbook::{PlaybookLine, Target};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+// Delimits a header of comment lines, in any playbook
+const COMMENT_DELIMITER: &str = "# ---";
+// Delimits a YAML header in Markdown files
+const DELIMITER: &str = "---";
+const MARKDOWN_EXTENSIONS: [&str; 2] = ["md", "markdown"];
+
+// Only the beginning of the files is read to show their titles in the picker
+const TITLE_READ_LIMIT: u64 = 4096;
+
+// File-level settings, declared in a YAML header at the top of the playbook
+#[derive(Default, Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct FrontMatter {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    // Values suggested for the variables of all steps
+    #[serde(default)]
+    pub vars: BTreeMap<String, String>,
+    // Target of the steps without one, `pane: <title>`, `tab: <name>` or a pane title
+    pub target: Option<String>,
+    // Directory to `cd` into with the first step sent
+    pub cwd: Option<String>,
+    pub ignore_comments: Option<bool>,
+    // Read `## ` lines as section headers, by default only when comments are ignored
+    pub sections: Option<bool>,
+}
+
+impl FrontMatter {
+    // Fills in the settings the step doesn't declare itself
+    pub(crate) fn apply(&self, line: &mut PlaybookLine) {
+        for (name, value) in &self.vars {
+            line.vars
+                .entry(name.clone())
+                .or_insert_with(|| value.clone());
+        }
+        if line.target.is_none() {
+            line.target = self.target.as_deref().map(Target::parse);
+        }
+    }
+}
+
+// Returns the front matter and the content with the header lines blanked, to keep the line numbers
+pub(crate) fn split(
+    file_path: &Path,
+    content: &str,
+) -> Result<(Option<FrontMatter>, String), String> {
+    let markdown = file_path
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| MARKDOWN_EXTENSIONS.contains(&e));
+    let Some((header, end)) = header(content, markdown) else {
+        return Ok((None, content.to_string()));
+    };
+    let front_matter = serde_yaml::from_str::<FrontMatter>(&header)
+        .map_err(|e| format!("invalid front matter: {}", e))?;
+
+    let rest = content
+        .split_inclusive('\n')
+        .enumerate()
+        .map(|(i, l)| if i <= end { "\n" } else { l })
+        .collect();
+    Ok((Some(front_matter), rest))
+}
+
+// The YAML text of the header and the 0-based index of its closing delimiter
+fn header(content: &str, markdown: bool) -> Option<(String, usize)> {
+    let mut lines = content.lines().enumerate();
+    let (_, first) = lines.find(|(_, l)| !l.trim().is_empty())?;
+    let commented = match first.trim_end() {
+        COMMENT_DELIMITER => true,
+        DELIMITER if markdown => false,
+        _ => return None,
+    };
+
+    let mut header = String::new();
+    for (i, line) in lines {
+        let line = line.trim_end();
+        if commented {
+            if line == COMMENT_DELIMITER {
+                return Some((header, i));
+            }
+            // Header lines are comments, `# key: value`
+            let line = line.strip_prefix('#')?;
+            header.push_str(line.strip_prefix(' ').unwrap_or(line));
+        } else {
+            if line == DELIMITER {
+                return Some((header, i));
+            }
+            header.push_str(line);
+        }
+        header.push('\n');
+    }
+    None
+}
+
+// The title declared by the file, if it has a valid front matter
+pub(crate) fn read_title(path: &Path) -> Option<String> {
+    let mut content = String::new();
+    fs::File::open(path)
+        .ok()?
+        .take(TITLE_READ_LIMIT)
+        .read_to_string(&mut content)
+        .ok()?;
+    let (front_matter, _) = split(path, &content).ok()?;
+    front_matter?.title
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split_playbook(content: &str) -> Result<(Option<FrontMatter>, String), String> {
+        split(Path::new("deploy.playbook"), content)
+    }
+
+    #[test]
+    fn commented_header_is_read() {
+        let content = "\n# ---\n# title: Deploy\n# vars:\n#   env: staging\n# target: 'tab: ops'\n# ---\nmake deploy\n";
+        let (front_matter, rest) = split_playbook(content).unwrap();
+        let front_matter = front_matter.unwrap();
+        assert_eq!(front_matter.title.as_deref(), Some("Deploy"));
+        assert_eq!(front_matter.vars["env"], "staging");
+        assert_eq!(front_matter.target.as_deref(), Some("tab: ops"));
+        // The header lines are blanked, so that the steps keep their line numbers
+        assert_eq!(rest, "\n\n\n\n\n\n\nmake deploy\n");
+    }
+
+    #[test]
+    fn markdown_header_is_read() {
+        let content = "---\ntitle: Runbook\nignore_comments: false\n---\n```sh\nls\n```\n";
+        let (front_matter, rest) = split(Path::new("runbook.md"), content).unwrap();
+        let front_matter = front_matter.unwrap();
+        assert_eq!(front_matter.title.as_deref(), Some("Runbook"));
+        assert_eq!(front_matter.ignore_comments, Some(false));
+        assert_eq!(rest.lines().count(), content.lines().count());
+
+        // `---` only delimits headers of Markdown files
+        let (front_matter, rest) = split_playbook(content).unwrap();
+        assert!(front_matter.is_none());
+        assert_eq!(rest, content);
+    }
+
+    #[test]
+    fn other_files_have_no_header() {
+        for content in [
+            "",
+            "ls\n# ---\n# title: late\n# ---\n",
+            "# ---\n# title: unclosed\nls\n",
+            "# ---\n# title: interrupted\nls\n# ---\n",
+        ] {
+            let (front_matter, rest) = split_playbook(content).unwrap();
+            assert!(front_matter.is_none(), "{:?}", content);
+            assert_eq!(rest, content);
+        }
+    }
+
+    #[test]
+    fn invalid_header_is_reported() {
+        let error = split_playbook("# ---\n# Author: me\n# ---\nls\n").unwrap_err();
+        assert!(
+            error.starts_with("invalid front matter: unknown field `Author`"),
+            "{}",
+            error
+        );
+        assert!(split_playbook("# ---\n# sections: maybe\n# ---\n").is_err());
+    }
+
+    #[test]
+    fn defaults_fill_in_missing_settings() {
+        let (front_matter, _) =
+            split_playbook("# ---\n# vars: {env: staging, user: me}\n# target: db\n# ---\n")
+                .unwrap();
+        let mut line = PlaybookLine {
+            target: Some(Target::Tab("ops".to_string())),
+            vars: BTreeMap::from([("env".to_string(), "prod".to_string())]),
+            ..Default::default()
+        };
+        front_matter.unwrap().apply(&mut line);
+        assert_eq!(line.target, Some(Target::Tab("ops".to_string())));
+        assert_eq!(line.vars["env"], "prod");
+        assert_eq!(line.vars["user"], "me");
+    }
+}
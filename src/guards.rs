@@ -0,0 +1,257 @@
+use crate::playbook::PlaybookLine;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fmt::Formatter;
+use std::path::Path;
+
+const KIND_ENV: &str = "env";
+const KIND_FILE: &str = "file";
+const KIND_HOST: &str = "host";
+const KIND_OS: &str = "os";
+
+// Prints the host name, the OS and the environment of the Zellij session, which the plugin can't read
+pub(crate) const FACTS_SCRIPT: &str = "uname -n; uname -s; env";
+
+// A condition for a step to be sent, declared with `# @if <kind>:<value>` or `# @only <kind>=<pattern>`
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Guard {
+    // The variable is set and not empty
+    Env(String),
+    // The file exists, relative to the plugin directory
+    File(String),
+    // The host name matches the pattern, where `*` matches any text and `?` any character
+    Host(String),
+    // The OS, as printed by `uname -s`, matches the pattern, ignoring case
+    Os(String),
+}
+
+impl fmt::Display for Guard {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Env(name) => write!(f, "{}:{}", KIND_ENV, name),
+            Self::File(path) => write!(f, "{}:{}", KIND_FILE, path),
+            Self::Host(pattern) => write!(f, "{}={}", KIND_HOST, pattern),
+            Self::Os(pattern) => write!(f, "{}={}", KIND_OS, pattern),
+        }
+    }
+}
+
+impl Guard {
+    // `env:NAME` or `file:PATH`
+    pub(crate) fn parse_if(text: &str) -> Option<Self> {
+        let (kind, value) = text.split_once(':')?;
+        let value = value.trim().to_string();
+        if value.is_empty() {
+            return None;
+        }
+        match kind.trim() {
+            KIND_ENV => Some(Self::Env(value)),
+            KIND_FILE => Some(Self::File(value)),
+            _ => None,
+        }
+    }
+
+    // `host=PATTERN` or `os=PATTERN`
+    pub(crate) fn parse_only(text: &str) -> Option<Self> {
+        let (kind, value) = text.split_once('=')?;
+        let value = value.trim().to_string();
+        if value.is_empty() {
+            return None;
+        }
+        match kind.trim() {
+            KIND_HOST => Some(Self::Host(value)),
+            KIND_OS => Some(Self::Os(value)),
+            _ => None,
+        }
+    }
+
+    // Files are checked by the plugin, the rest needs the facts of the host
+    pub(crate) fn needs_host(&self) -> bool {
+        !matches!(self, Self::File(_))
+    }
+
+    // Returns why the guard is false, if it is
+    fn check(&self, facts: Option<&HostFacts>, cwd: &Path) -> Option<String> {
+        if let Self::File(path) = self {
+            if cwd.join(path).exists() {
+                return None;
+            }
+            return Some(format!("no file {}", path));
+        }
+
+        let Some(facts) = facts else {
+            return Some("waiting for the host".to_string());
+        };
+        match self {
+            Self::Env(name) if facts.env.get(name).is_some_and(|v| !v.is_empty()) => None,
+            Self::Env(name) => Some(format!("{} is not set", name)),
+            Self::Host(pattern) if glob_match(pattern, &facts.hostname) => None,
+            Self::Host(pattern) => Some(format!("host {} is not {}", facts.hostname, pattern)),
+            Self::Os(pattern) if glob_match(&pattern.to_lowercase(), &facts.os.to_lowercase()) => {
+                None
+            }
+            Self::Os(pattern) => Some(format!("OS {} is not {}", facts.os, pattern)),
+            Self::File(_) => None,
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone)]
+pub(crate) struct HostFacts {
+    hostname: String,
+    os: String,
+    env: BTreeMap<String, String>,
+}
+
+// Parses the output of the facts script. Lines of multi-line values that don't look like `NAME=value` are skipped.
+pub(crate) fn parse_facts(output: &str) -> HostFacts {
+    let mut lines = output.lines();
+    let hostname = lines.next().unwrap_or_default().trim().to_string();
+    let os = lines.next().unwrap_or_default().trim().to_string();
+    let env = lines
+        .filter_map(|l| l.split_once('='))
+        .filter(|(name, _)| {
+            !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        })
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+    HostFacts { hostname, os, env }
+}
+
+// Marks the steps with a false guard as skipped, with the reason
+pub(crate) fn evaluate(lines: &mut [PlaybookLine], facts: Option<&HostFacts>, cwd: &Path) {
+    for line in lines {
+        line.skipped = line.guards.iter().find_map(|g| g.check(facts, cwd));
+    }
+}
+
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+
+    // Position after the last `*` in the pattern and the text it was tried at
+    let (mut p, mut t) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p + 1, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                // Let the `*` match one more character
+                Some((after_star, tried)) => {
+                    p = after_star;
+                    t = tried + 1;
+                    star = Some((after_star, tried + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob_patterns_match() {
+        for (pattern, text) in [
+            ("web-01", "web-01"),
+            ("web-*", "web-01"),
+            ("*", ""),
+            ("*-01", "web-01"),
+            ("w?b-*1", "web-01"),
+            ("*b*0*", "web-01"),
+            ("a*b*c", "abxbc"),
+            ("**", "anything"),
+        ] {
+            assert!(glob_match(pattern, text), "{} {}", pattern, text);
+        }
+        for (pattern, text) in [
+            ("web", "web-01"),
+            ("web-?", "web-01"),
+            ("db-*", "web-01"),
+            ("*-02", "web-01"),
+            ("a*b*c", "abxbcx"),
+            ("?", ""),
+        ] {
+            assert!(!glob_match(pattern, text), "{} {}", pattern, text);
+        }
+    }
+
+    #[test]
+    fn facts_are_parsed() {
+        let facts = parse_facts(
+            "web-01\nLinux\nHOME=/home/me\nEMPTY=\nPS1=a=b\nline of a\nmulti-line value\nnot a name=x\n",
+        );
+        assert_eq!(facts.hostname, "web-01");
+        assert_eq!(facts.os, "Linux");
+        assert_eq!(facts.env["HOME"], "/home/me");
+        assert_eq!(facts.env["EMPTY"], "");
+        assert_eq!(facts.env["PS1"], "a=b");
+        assert_eq!(facts.env.len(), 3);
+    }
+
+    #[test]
+    fn guards_are_parsed() {
+        assert_eq!(
+            Guard::parse_if("env: CI"),
+            Some(Guard::Env("CI".to_string()))
+        );
+        assert_eq!(
+            Guard::parse_if("file:Cargo.toml"),
+            Some(Guard::File("Cargo.toml".to_string()))
+        );
+        assert_eq!(Guard::parse_if("env:"), None);
+        assert_eq!(Guard::parse_if("host:web"), None);
+        assert_eq!(
+            Guard::parse_only("os = linux"),
+            Some(Guard::Os("linux".to_string()))
+        );
+        assert_eq!(Guard::parse_only("env=CI"), None);
+    }
+
+    #[test]
+    fn steps_with_false_guards_are_skipped() {
+        let facts = parse_facts("web-01\nLinux\nCI=true\n");
+        let guards = [
+            (Guard::Env("CI".to_string()), None),
+            (Guard::Env("DEPLOY".to_string()), Some("DEPLOY is not set")),
+            (Guard::Host("web-*".to_string()), None),
+            (
+                Guard::Host("db-*".to_string()),
+                Some("host web-01 is not db-*"),
+            ),
+            (Guard::Os("LIN*".to_string()), None),
+            (
+                Guard::Os("darwin".to_string()),
+                Some("OS Linux is not darwin"),
+            ),
+            (
+                Guard::File("no-such-file".to_string()),
+                Some("no file no-such-file"),
+            ),
+        ];
+        let mut lines: Vec<_> = guards
+            .iter()
+            .map(|(guard, _)| PlaybookLine {
+                guards: vec![guard.clone()],
+                ..Default::default()
+            })
+            .collect();
+        evaluate(&mut lines, Some(&facts), Path::new("."));
+        for (line, (_, skipped)) in lines.iter().zip(guards) {
+            assert_eq!(line.skipped.as_deref(), skipped);
+        }
+
+        evaluate(&mut lines[..1], None, Path::new("."));
+        assert_eq!(lines[0].skipped.as_deref(), Some("waiting for the host"));
+    }
+}
@@ -0,0 +1,359 @@
+use crate::variables;
+use std::ops::Range;
+
+// Longest first, so that `&&` is not read as two `&`
+const SEPARATORS: [&str; 9] = ["&&", "||", "|&", ";;", "$(", "|", ";", "&", "("];
+const REDIRECTIONS: [&str; 8] = ["&>>", "<<<", "&>", ">>", ">&", "<<", ">", "<"];
+
+// Parts of a shell command colored in the list
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Token {
+    Command,
+    Flag,
+    String,
+    Variable,
+    // Pipes, separators and redirections
+    Operator,
+    Comment,
+    // `{{name}}` variables of the playbook, still to be filled in
+    Placeholder,
+    // Tags of structured steps, shown after their label
+    Tag,
+}
+
+// Splits a command into colored parts, as character ranges. Plain arguments are left out.
+// This is a best effort for display only, unbalanced quotes run to the end of the text.
+pub(crate) fn tokenize(text: &str) -> Vec<(Token, Range<usize>)> {
+    let placeholders: Vec<Range<usize>> = variables::placeholders(text)
+        .into_iter()
+        .map(|(range, _)| range)
+        .collect();
+    let mut scanner = Scanner {
+        text,
+        placeholders,
+        tokens: Vec::new(),
+        i: 0,
+    };
+    scanner.run();
+
+    // Byte offsets to character offsets
+    let mut offsets = vec![0; text.len() + 1];
+    for (n, (i, c)) in text.char_indices().enumerate() {
+        offsets[i..i + c.len_utf8()].fill(n);
+    }
+    offsets[text.len()] = text.chars().count();
+    scanner
+        .tokens
+        .into_iter()
+        .filter(|(_, r)| !r.is_empty())
+        .map(|(token, r)| (token, offsets[r.start]..offsets[r.end]))
+        .collect()
+}
+
+struct Scanner<'a> {
+    text: &'a str,
+    placeholders: Vec<Range<usize>>,
+    tokens: Vec<(Token, Range<usize>)>,
+    // Byte offset of the next character
+    i: usize,
+}
+
+impl Scanner<'_> {
+    fn rest(&self) -> &str {
+        &self.text[self.i..]
+    }
+
+    fn next_char(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn placeholder_end(&self) -> Option<usize> {
+        self.placeholders
+            .iter()
+            .find(|r| r.start == self.i)
+            .map(|r| r.end)
+    }
+
+    fn run(&mut self) {
+        let mut command_expected = true;
+        let mut redirect_target = false;
+        let mut in_backquotes = false;
+        while let Some(c) = self.next_char() {
+            if c.is_whitespace() {
+                self.i += c.len_utf8();
+                continue;
+            }
+            if c == '#' {
+                self.tokens.push((Token::Comment, self.i..self.text.len()));
+                return;
+            }
+            if let Some(separator) = SEPARATORS.iter().find(|s| self.rest().starts_with(*s)) {
+                self.push(Token::Operator, separator.len());
+                command_expected = true;
+                continue;
+            }
+            if let Some(len) = self.redirection_len() {
+                self.push(Token::Operator, len);
+                redirect_target = true;
+                continue;
+            }
+            if c == ')' || c == '`' {
+                self.push(Token::Operator, 1);
+                // A command follows an opening backquote only
+                if c == '`' {
+                    in_backquotes = !in_backquotes;
+                }
+                command_expected = c == '`' && in_backquotes;
+                continue;
+            }
+
+            let kind = if redirect_target {
+                None
+            } else if command_expected && self.is_assignment() {
+                // `NAME=value` before the command
+                let name_len = self.rest().find('=').unwrap_or_default();
+                self.push(Token::Variable, name_len);
+                self.word(None);
+                continue;
+            } else if command_expected {
+                Some(Token::Command)
+            } else if c == '-' {
+                Some(Token::Flag)
+            } else {
+                None
+            };
+            self.word(kind);
+            if !redirect_target {
+                command_expected = false;
+            }
+            redirect_target = false;
+        }
+    }
+
+    fn push(&mut self, token: Token, len: usize) {
+        self.tokens.push((token, self.i..self.i + len));
+        self.i += len;
+    }
+
+    // `>`, `>>`, `2>`, `&>`... with an optional file descriptor
+    fn redirection_len(&self) -> Option<usize> {
+        let digits = self.rest().chars().take_while(char::is_ascii_digit).count();
+        let rest = &self.rest()[digits..];
+        REDIRECTIONS
+            .iter()
+            .find(|r| rest.starts_with(*r))
+            .map(|r| digits + r.len())
+    }
+
+    fn is_assignment(&self) -> bool {
+        let name: String = self
+            .rest()
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric() || *c == '_')
+            .collect();
+        !name.is_empty()
+            && !name.starts_with(|c: char| c.is_ascii_digit())
+            && self.rest()[name.len()..].starts_with('=')
+    }
+
+    // A word up to the next blank or operator, made of plain, quoted and variable parts
+    fn word(&mut self, kind: Option<Token>) {
+        let mut plain_start = self.i;
+        while let Some(c) = self.next_char() {
+            let ends_word = c.is_whitespace()
+                || c == ')'
+                || c == '`'
+                || SEPARATORS.iter().any(|s| self.rest().starts_with(s))
+                || REDIRECTIONS.iter().any(|r| self.rest().starts_with(r));
+            if ends_word {
+                break;
+            }
+
+            let part_start = self.i;
+            if let Some(end) = self.placeholder_end() {
+                self.i = end;
+                self.flush(kind, plain_start..part_start);
+                self.tokens.push((Token::Placeholder, part_start..end));
+            } else if c == '\'' {
+                self.flush(kind, plain_start..part_start);
+                self.single_quoted();
+            } else if c == '"' {
+                self.flush(kind, plain_start..part_start);
+                self.double_quoted();
+            } else if c == '$' && self.variable_len() > 1 {
+                self.flush(kind, plain_start..part_start);
+                let len = self.variable_len();
+                self.push(Token::Variable, len);
+            } else if c == '\\' {
+                self.i += 1;
+                self.i += self.next_char().map_or(0, char::len_utf8);
+                continue;
+            } else {
+                self.i += c.len_utf8();
+                continue;
+            }
+            plain_start = self.i;
+        }
+        self.flush(kind, plain_start..self.i);
+    }
+
+    fn flush(&mut self, kind: Option<Token>, range: Range<usize>) {
+        if let Some(kind) = kind {
+            self.tokens.push((kind, range));
+        }
+    }
+
+    fn single_quoted(&mut self) {
+        let start = self.i;
+        self.i = self.rest()[1..]
+            .find('\'')
+            .map_or(self.text.len(), |end| self.i + end + 2);
+        self.tokens.push((Token::String, start..self.i));
+    }
+
+    // Variables and placeholders within the string are colored as such
+    fn double_quoted(&mut self) {
+        let mut start = self.i;
+        self.i += 1;
+        while let Some(c) = self.next_char() {
+            let part_start = self.i;
+            if c == '"' {
+                self.i += 1;
+                break;
+            } else if c == '\\' {
+                self.i += 1;
+                self.i += self.next_char().map_or(0, char::len_utf8);
+                continue;
+            } else if let Some(end) = self.placeholder_end() {
+                self.tokens.push((Token::String, start..part_start));
+                self.tokens.push((Token::Placeholder, part_start..end));
+                self.i = end;
+            } else if c == '$' && self.variable_len() > 1 {
+                self.tokens.push((Token::String, start..part_start));
+                let len = self.variable_len();
+                self.push(Token::Variable, len);
+            } else {
+                self.i += c.len_utf8();
+                continue;
+            }
+            start = self.i;
+        }
+        self.tokens.push((Token::String, start..self.i));
+    }
+
+    // `$NAME`, `${NAME}`, `$1` or `$?`, 1 for a lone `$`
+    fn variable_len(&self) -> usize {
+        let rest = &self.rest()[1..];
+        if rest.starts_with('{') {
+            return rest.find('}').map_or(self.rest().len(), |end| end + 2);
+        }
+        match rest.chars().next() {
+            Some(c) if c.is_ascii_digit() || "?#@*!$-".contains(c) => 2,
+            _ => {
+                1 + rest
+                    .chars()
+                    .take_while(|c| c.is_ascii_alphanumeric() || *c == '_')
+                    .count()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The text of each token, to compare tokens at a glance
+    fn parts(text: &str) -> Vec<(Token, String)> {
+        let chars: Vec<char> = text.chars().collect();
+        tokenize(text)
+            .into_iter()
+            .map(|(token, range)| (token, chars[range].iter().collect()))
+            .collect()
+    }
+
+    fn part(token: Token, text: &str) -> (Token, String) {
+        (token, text.to_string())
+    }
+
+    #[test]
+    fn command_flags_and_pipes() {
+        assert_eq!(
+            parts("ls -la | grep foo"),
+            vec![
+                part(Token::Command, "ls"),
+                part(Token::Flag, "-la"),
+                part(Token::Operator, "|"),
+                part(Token::Command, "grep"),
+            ]
+        );
+    }
+
+    #[test]
+    fn assignments_redirections_and_comments() {
+        assert_eq!(
+            parts("FOO=1 make 2>&1 > out.log # build"),
+            vec![
+                part(Token::Variable, "FOO"),
+                part(Token::Command, "make"),
+                part(Token::Operator, "2>&"),
+                part(Token::Operator, ">"),
+                part(Token::Comment, "# build"),
+            ]
+        );
+    }
+
+    #[test]
+    fn variables_and_placeholders_within_strings() {
+        assert_eq!(
+            parts(r#"echo "$HOME/{{dir}}" 'it $s'"#),
+            vec![
+                part(Token::Command, "echo"),
+                part(Token::String, "\""),
+                part(Token::Variable, "$HOME"),
+                part(Token::String, "/"),
+                part(Token::Placeholder, "{{dir}}"),
+                part(Token::String, "\""),
+                part(Token::String, "'it $s'"),
+            ]
+        );
+    }
+
+    #[test]
+    fn unterminated_braced_variable_runs_to_the_end() {
+        assert_eq!(
+            parts("echo ${HOME x"),
+            vec![
+                part(Token::Command, "echo"),
+                part(Token::Variable, "${HOME x"),
+            ]
+        );
+    }
+
+    #[test]
+    fn unterminated_quotes_run_to_the_end() {
+        assert_eq!(
+            parts("echo 'abc | def"),
+            vec![
+                part(Token::Command, "echo"),
+                part(Token::String, "'abc | def")
+            ]
+        );
+        assert_eq!(
+            parts(r#"echo "abc $X"#),
+            vec![
+                part(Token::Command, "echo"),
+                part(Token::String, "\"abc "),
+                part(Token::Variable, "$X"),
+            ]
+        );
+    }
+
+    #[test]
+    fn ranges_are_in_characters() {
+        assert_eq!(
+            tokenize("é -x"),
+            vec![(Token::Command, 0..1), (Token::Flag, 2..4)]
+        );
+    }
+}
@@ -0,0 +1,539 @@
+use crate::pipe::Action;
+use crate::playbook::PlaybookLine;
+use crate::tab_manager::TabManager;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+use zellij_tile::prelude::*;
+
+pub(crate) const PLAYBOOK_SOURCE: &str = "playbook";
+pub(crate) const BROADCAST_SOURCE: &str = "broadcast";
+pub(crate) const CHECK_SOURCE: &str = "check";
+
+// Older entries are dropped from memory once the history grows beyond this, the log keeps them
+const MAX_ENTRIES: usize = 500;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) enum Status {
+    Pending,
+    Done(String),
+    Failed(String),
+    // Identical to a text still waiting to be pasted
+    Dropped,
+}
+
+impl From<Result<String, String>> for Status {
+    fn from(result: Result<String, String>) -> Self {
+        match result {
+            Ok(pane) => Self::Done(pane),
+            Err(e) => Self::Failed(e),
+        }
+    }
+}
+
+impl Status {
+    fn text(&self) -> String {
+        match self {
+            Self::Pending => "pending".to_string(),
+            Self::Done(pane) => format!("ok -> {}", pane),
+            Self::Failed(e) => format!("FAILED: {}", e),
+            Self::Dropped => "dropped as duplicate".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct HistoryEntry {
+    #[serde(skip)]
+    pub id: usize,
+    // Seconds since the Unix epoch
+    pub timestamp: u64,
+    pub source: String,
+    pub action: Action,
+    // Playbook the step was taken from
+    pub file: Option<String>,
+    // The text as it was sent
+    pub line: PlaybookLine,
+    pub newline: bool,
+    pub exit_code: Option<i32>,
+    pub status: Status,
+    // Plugin instance that sent the text and the id of the entry there, shared by the updates of the entry in the log
+    #[serde(default)]
+    key: (u32, usize),
+}
+
+impl HistoryEntry {
+    pub(crate) fn new(source: String, action: Action, line: PlaybookLine, newline: bool) -> Self {
+        Self {
+            id: 0,
+            timestamp: now(),
+            source,
+            action,
+            file: None,
+            line,
+            newline,
+            exit_code: None,
+            status: Status::Pending,
+            key: (0, 0),
+        }
+    }
+
+    pub(crate) fn label(&self) -> String {
+        format!("{}: {}", self.outcome(), self.line.content)
+    }
+
+    // When and how the text was sent, and what came of it
+    pub(crate) fn outcome(&self) -> String {
+        format!(
+            "{} [{}] {} ({})",
+            format_time(self.timestamp),
+            self.source,
+            self.action,
+            self.status.text()
+        )
+    }
+
+    // The text reached a pane, as opposed to failed, dropped, appended or checked entries
+    pub(crate) fn is_delivered(&self) -> bool {
+        matches!(self.status, Status::Done(_))
+            && matches!(self.action, Action::Paste | Action::Insert | Action::Run)
+            && self.source != CHECK_SOURCE
+    }
+
+    // Where the step comes from, as `file, step N`
+    fn origin(&self) -> Option<String> {
+        let file = self.file.as_ref()?;
+        if self.line.id == 0 {
+            return Some(file.clone());
+        }
+        Some(format!("{}, step {}", file, self.line.id))
+    }
+}
+
+// Sent texts, newest last. Entries are also appended to a log shared by all plugin instances,
+// when they are sent and again when they finish.
+#[derive(Default, Debug, Clone)]
+pub(crate) struct History {
+    entries: VecDeque<HistoryEntry>,
+    last_id: usize,
+    log: Option<PathBuf>,
+    // Id of the plugin instance, to tell its entries apart in the log
+    instance: u32,
+    // Entries read from the log, oldest first, and the length of the log they were read from
+    logged: Vec<HistoryEntry>,
+    log_offset: u64,
+    // Position of the logged entries by key, to merge their updates
+    logged_index: HashMap<(u32, usize), usize>,
+}
+
+impl History {
+    pub(crate) fn with_log(log: PathBuf, instance: u32) -> Self {
+        Self {
+            log: Some(log),
+            instance,
+            ..Default::default()
+        }
+    }
+
+    // Returns the id of the new entry, which is written to the log as pending
+    pub(crate) fn push(
+        &mut self,
+        mut entry: HistoryEntry,
+    ) -> (usize, Result<(), Box<dyn std::error::Error>>) {
+        self.last_id += 1;
+        entry.id = self.last_id;
+        entry.key = (self.instance, self.last_id);
+        let result = self.write_log(&entry);
+        self.entries.push_back(entry);
+        if self.entries.len() > MAX_ENTRIES {
+            self.entries.pop_front();
+        }
+        (self.last_id, result)
+    }
+
+    pub(crate) fn set_exit_code(&mut self, id: usize, exit_code: Option<i32>) {
+        if let Some(entry) = self.entries.iter_mut().find(|e| e.id == id) {
+            entry.exit_code = exit_code;
+        }
+    }
+
+    // Records where the text went, or why it failed, and writes the update of the entry to the log
+    pub(crate) fn finish(
+        &mut self,
+        id: usize,
+        status: Status,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(entry) = self.entries.iter_mut().find(|e| e.id == id) else {
+            return Ok(());
+        };
+        entry.status = status;
+        let entry = entry.clone();
+        self.write_log(&entry)
+    }
+
+    fn write_log(&self, entry: &HistoryEntry) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(log) = &self.log else {
+            return Ok(());
+        };
+        let mut file = fs::OpenOptions::new().create(true).append(true).open(log)?;
+        writeln!(file, "{}", serde_json::to_string(entry)?)?;
+        Ok(())
+    }
+
+    // The newest entry of the step of the playbook
+    pub(crate) fn last_of_step(&self, file: &str, id: usize) -> Option<&HistoryEntry> {
+        self.entries
+            .iter()
+            .rev()
+            .find(|e| e.line.id == id && e.file.as_deref() == Some(file))
+    }
+
+    // Entries of this instance, newest first
+    pub(crate) fn manager(&self) -> TabManager<HistoryEntry> {
+        TabManager::new(self.entries.iter().rev().cloned().collect())
+    }
+
+    // Entries logged by all instances, newest first and numbered from the oldest.
+    // Only the lines appended since the last call are read, an update replaces the entry in place.
+    pub(crate) fn load_log(&mut self) -> Result<Vec<HistoryEntry>, Box<dyn std::error::Error>> {
+        let Some(log) = &self.log else {
+            return Ok(Vec::new());
+        };
+        let mut file = match fs::File::open(log) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                self.logged.clear();
+                self.logged_index.clear();
+                self.log_offset = 0;
+                return Ok(Vec::new());
+            }
+            Err(e) => return Err(e.into()),
+        };
+        // The log was removed and started again
+        if file.metadata()?.len() < self.log_offset {
+            self.logged.clear();
+            self.logged_index.clear();
+            self.log_offset = 0;
+        }
+        file.seek(SeekFrom::Start(self.log_offset))?;
+        let mut appended = Vec::new();
+        file.read_to_end(&mut appended)?;
+
+        // A line still being written is read next time
+        let complete = appended
+            .iter()
+            .rposition(|b| *b == b'\n')
+            .map_or(0, |i| i + 1);
+        self.log_offset += complete as u64;
+        for line in String::from_utf8_lossy(&appended[..complete]).lines() {
+            // Lines cut by a concurrent write are skipped
+            if let Ok(entry) = serde_json::from_str::<HistoryEntry>(line) {
+                self.add_logged(entry);
+            }
+        }
+
+        Ok(self.logged.iter().rev().cloned().collect())
+    }
+
+    fn add_logged(&mut self, mut entry: HistoryEntry) {
+        match self.logged_index.get(&entry.key) {
+            Some(&i) => {
+                entry.id = self.logged[i].id;
+                self.logged[i] = entry;
+            }
+            None => {
+                entry.id = self.logged.len() + 1;
+                // Entries logged without a key can't be updated
+                if entry.key != (0, 0) {
+                    self.logged_index.insert(entry.key, self.logged.len());
+                }
+                self.logged.push(entry);
+            }
+        }
+    }
+}
+
+// Texts sent after the log position that didn't reach a pane, or are still on their way
+pub(crate) fn undelivered_count(entries: &[HistoryEntry], since: usize) -> usize {
+    entries
+        .iter()
+        .filter(|e| e.id > since && e.source != CHECK_SOURCE)
+        .filter(|e| matches!(e.status, Status::Pending | Status::Failed(_)))
+        .filter(|e| matches!(e.action, Action::Paste | Action::Insert | Action::Run))
+        .count()
+}
+
+// Steps of a macro: the texts delivered after the log position, oldest first.
+// A broadcast is logged once per pane but recorded as a single step.
+pub(crate) fn recorded_steps(entries: &[HistoryEntry], since: usize) -> Vec<PlaybookLine> {
+    let mut entries: Vec<&HistoryEntry> = entries.iter().filter(|e| e.id > since).collect();
+    entries.sort_by_key(|e| e.id);
+
+    let mut steps: Vec<PlaybookLine> = Vec::new();
+    let mut last_broadcast: Option<u64> = None;
+    for entry in entries {
+        if !entry.is_delivered() {
+            continue;
+        }
+        if entry.source == BROADCAST_SOURCE {
+            if last_broadcast == Some(entry.timestamp)
+                && steps
+                    .last()
+                    .is_some_and(|s| s.content == entry.line.content)
+            {
+                continue;
+            }
+            last_broadcast = Some(entry.timestamp);
+        }
+        let mut step = PlaybookLine::new(steps.len() + 1, entry.line.content.clone());
+        // Broadcast targets are not a single pane
+        if entry.source != BROADCAST_SOURCE {
+            step.target = entry.line.target.clone();
+        }
+        steps.push(step);
+    }
+    steps
+}
+
+pub(crate) fn source_label(source: &PipeSource) -> String {
+    match source {
+        PipeSource::Cli(_) => "cli".to_string(),
+        PipeSource::Plugin(plugin_id) => format!("plugin {}", plugin_id),
+        PipeSource::Keybind => "keybind".to_string(),
+    }
+}
+
+// Renders the entries, oldest first, as a shell script or as a Markdown document
+pub(crate) fn transcript(entries: &[HistoryEntry], shell: bool) -> String {
+    let mut entries: Vec<&HistoryEntry> = entries.iter().collect();
+    entries.sort_by_key(|e| (e.timestamp, e.id));
+
+    let exported = format!(
+        "exported {}, {} step(s)",
+        format_date_time(now()),
+        entries.len()
+    );
+    let mut out = if shell {
+        format!("#!/bin/sh\n# Session transcript {}\n", exported)
+    } else {
+        format!("# Session transcript\n\n_{}_\n", exported)
+    };
+
+    for (i, entry) in entries.iter().enumerate() {
+        let heading = format!(
+            "{}. {} [{}] {} ({})",
+            i + 1,
+            format_date_time(entry.timestamp),
+            entry.source,
+            entry.action,
+            entry.status.text()
+        );
+        if shell {
+            out.push_str(&format!("\n# {}\n", heading));
+            if let Some(origin) = entry.origin() {
+                out.push_str(&format!("# Playbook: {}\n", origin));
+            }
+            // Only the texts that reached a pane are replayed by the script
+            if entry.is_delivered() {
+                out.push_str(&format!("{}\n", entry.line.content));
+            } else {
+                for line in entry.line.content.lines() {
+                    out.push_str(&format!("# {}\n", line));
+                }
+            }
+        } else {
+            out.push_str(&format!("\n## {}\n\n", heading));
+            if let Some(origin) = entry.origin() {
+                out.push_str(&format!("- Playbook: {}\n", origin));
+            }
+            if let Some(exit_code) = entry.exit_code {
+                out.push_str(&format!("- Exit code: {}\n", exit_code));
+            }
+            out.push_str(&format!("\n```sh\n{}\n```\n", entry.line.content));
+        }
+    }
+    out
+}
+
+pub(crate) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+fn format_time(timestamp: u64) -> String {
+    let seconds = timestamp % 86400;
+    format!(
+        "{:02}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+// `YYYY-MM-DD HH:MM:SS UTC`
+pub(crate) fn format_date_time(timestamp: u64) -> String {
+    let (year, month, day) = civil_from_days((timestamp / 86400) as i64);
+    format!(
+        "{:04}-{:02}-{:02} {} UTC",
+        year,
+        month,
+        day,
+        format_time(timestamp)
+    )
+}
+
+// `YYYYMMDD-HHMMSS`, to be used in file names
+pub(crate) fn format_file_stamp(timestamp: u64) -> String {
+    let (year, month, day) = civil_from_days((timestamp / 86400) as i64);
+    let seconds = timestamp % 86400;
+    format!(
+        "{:04}{:02}{:02}-{:02}{:02}{:02}",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+// Converts days since the Unix epoch to a (year, month, day) date of the proleptic Gregorian calendar
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(
+        id: usize,
+        timestamp: u64,
+        source: &str,
+        content: &str,
+        status: Status,
+    ) -> HistoryEntry {
+        HistoryEntry {
+            id,
+            timestamp,
+            status,
+            ..HistoryEntry::new(
+                source.to_string(),
+                Action::Run,
+                PlaybookLine::new(3, content.to_string()),
+                true,
+            )
+        }
+    }
+
+    #[test]
+    fn days_are_converted_to_dates() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+        assert_eq!(civil_from_days(59), (1970, 3, 1));
+        assert_eq!(civil_from_days(11016), (2000, 2, 29));
+        assert_eq!(civil_from_days(19782), (2024, 2, 29));
+        assert_eq!(civil_from_days(20744), (2026, 10, 18));
+    }
+
+    #[test]
+    fn timestamps_are_formatted() {
+        assert_eq!(format_date_time(0), "1970-01-01 00:00:00 UTC");
+        assert_eq!(format_date_time(1_709_210_096), "2024-02-29 12:34:56 UTC");
+        assert_eq!(format_file_stamp(1_709_210_096), "20240229-123456");
+    }
+
+    #[test]
+    fn shell_transcript_replays_delivered_texts() {
+        let mut failed = entry(
+            2,
+            200,
+            "cli",
+            "make\ndeploy",
+            Status::Failed("no pane".into()),
+        );
+        failed.file = Some("deploy.playbook".to_string());
+        let entries = [
+            failed,
+            entry(1, 100, PLAYBOOK_SOURCE, "ls", Status::Done("shell".into())),
+        ];
+        let transcript = transcript(&entries, true);
+        assert!(transcript.starts_with("#!/bin/sh\n# Session transcript exported "));
+        assert!(transcript.contains(", 2 step(s)\n"));
+        assert!(transcript.ends_with(
+            "\n# 1. 1970-01-01 00:01:40 UTC [playbook] run (ok -> shell)\nls\n\
+             \n# 2. 1970-01-01 00:03:20 UTC [cli] run (FAILED: no pane)\n\
+             # Playbook: deploy.playbook, step 3\n# make\n# deploy\n"
+        ));
+    }
+
+    #[test]
+    fn markdown_transcript_lists_all_texts() {
+        let mut checked = entry(
+            1,
+            100,
+            CHECK_SOURCE,
+            "cargo test",
+            Status::Done("check".into()),
+        );
+        checked.exit_code = Some(0);
+        let transcript = transcript(&[checked], false);
+        assert!(transcript.starts_with("# Session transcript\n\n_exported "));
+        assert!(transcript.ends_with(
+            "\n## 1. 1970-01-01 00:01:40 UTC [check] run (ok -> check)\n\n\
+             - Exit code: 0\n\n```sh\ncargo test\n```\n"
+        ));
+    }
+
+    #[test]
+    fn log_entries_are_written_when_sent_and_updated_when_finished() {
+        let log = std::env::temp_dir().join(format!("zellij-playbooks-test-{}.jsonl", now()));
+        let _ = fs::remove_file(&log);
+        let mut sender = History::with_log(log.clone(), 7);
+        let mut other = History::with_log(log.clone(), 8);
+
+        let (first, result) = sender.push(entry(0, 100, "cli", "make", Status::Pending));
+        result.unwrap();
+        let (second, _) = sender.push(entry(0, 101, "cli", "ls", Status::Pending));
+        other
+            .push(entry(0, 102, "cli", "pwd", Status::Pending))
+            .1
+            .unwrap();
+        let entries = other.load_log().unwrap();
+        assert_eq!(entries.len(), 3);
+        assert!(entries.iter().all(|e| e.status == Status::Pending));
+        assert_eq!(undelivered_count(&entries, 0), 3);
+
+        sender
+            .finish(first, Status::Failed("exit code 2".into()))
+            .unwrap();
+        sender.finish(second, Status::Done("shell".into())).unwrap();
+        let entries = other.load_log().unwrap();
+        let statuses: Vec<_> = entries.iter().map(|e| (e.id, e.status.clone())).collect();
+        assert_eq!(
+            statuses,
+            [
+                (3, Status::Pending),
+                (2, Status::Done("shell".into())),
+                (1, Status::Failed("exit code 2".into())),
+            ]
+        );
+        assert_eq!(undelivered_count(&entries, 1), 1);
+        assert_eq!(recorded_steps(&entries, 0).len(), 1);
+        fs::remove_file(&log).unwrap();
+    }
+}
@@ -7,12 +7,32 @@ use zellij_tile::prelude::*;
 const BIND_EDIT: &str = "bind_edit";
 const BIND_RELOAD: &str = "bind_reload";
 const BIND_SWITCH_FILTER_ID: &str = "bind_switch_filter_id";
+const BIND_MARK: &str = "bind_mark";
+const BIND_LAYOUT: &str = "bind_layout";
+const BIND_EDIT_STEP: &str = "bind_edit_step";
+const BIND_SAVE_STEP: &str = "bind_save_step";
+const BIND_APPEND: &str = "bind_append";
+const BIND_EXPORT: &str = "bind_export";
+const BIND_RECORD: &str = "bind_record";
+const BIND_EXPAND: &str = "bind_expand";
+const BIND_CHECK: &str = "bind_check";
+const BIND_DETAILS: &str = "bind_details";
 
 #[derive(Clone, Debug)]
 pub(crate) struct Keybindings {
     pub edit: Keybinding,
     pub reload: Keybinding,
     pub switch_filter_id: Keybinding,
+    pub mark: Keybinding,
+    pub layout: Keybinding,
+    pub edit_step: Keybinding,
+    pub save_step: Keybinding,
+    pub append: Keybinding,
+    pub export: Keybinding,
+    pub record: Keybinding,
+    pub expand: Keybinding,
+    pub check: Keybinding,
+    pub details: Keybinding,
 }
 
 impl Default for Keybindings {
@@ -21,6 +41,16 @@ impl Default for Keybindings {
             edit: Keybinding::new(KeyModifier::Ctrl, 'e'),
             reload: Keybinding::new(KeyModifier::Ctrl, 'r'),
             switch_filter_id: Keybinding::new(KeyModifier::Ctrl, 'i'),
+            mark: Keybinding::new(KeyModifier::Ctrl, 'a'),
+            layout: Keybinding::new(KeyModifier::Ctrl, 'l'),
+            edit_step: Keybinding::new(KeyModifier::Alt, 'e'),
+            save_step: Keybinding::new(KeyModifier::Alt, 's'),
+            append: Keybinding::new(KeyModifier::Alt, 'a'),
+            export: Keybinding::new(KeyModifier::Ctrl, 'd'),
+            record: Keybinding::new(KeyModifier::Alt, 'r'),
+            expand: Keybinding::new(KeyModifier::Ctrl, 'f'),
+            check: Keybinding::new(KeyModifier::Ctrl, 'x'),
+            details: Keybinding::new(KeyModifier::Ctrl, 'v'),
         }
     }
 }
@@ -38,6 +68,36 @@ impl Keybindings {
         if let Some(value) = conf.get(BIND_SWITCH_FILTER_ID) {
             default.switch_filter_id = parse_key_info(value)?
         }
+        if let Some(value) = conf.get(BIND_MARK) {
+            default.mark = parse_key_info(value)?
+        }
+        if let Some(value) = conf.get(BIND_LAYOUT) {
+            default.layout = parse_key_info(value)?
+        }
+        if let Some(value) = conf.get(BIND_EDIT_STEP) {
+            default.edit_step = parse_key_info(value)?
+        }
+        if let Some(value) = conf.get(BIND_SAVE_STEP) {
+            default.save_step = parse_key_info(value)?
+        }
+        if let Some(value) = conf.get(BIND_APPEND) {
+            default.append = parse_key_info(value)?
+        }
+        if let Some(value) = conf.get(BIND_EXPORT) {
+            default.export = parse_key_info(value)?
+        }
+        if let Some(value) = conf.get(BIND_RECORD) {
+            default.record = parse_key_info(value)?
+        }
+        if let Some(value) = conf.get(BIND_EXPAND) {
+            default.expand = parse_key_info(value)?
+        }
+        if let Some(value) = conf.get(BIND_CHECK) {
+            default.check = parse_key_info(value)?
+        }
+        if let Some(value) = conf.get(BIND_DETAILS) {
+            default.details = parse_key_info(value)?
+        }
 
         Ok(default)
     }
@@ -0,0 +1,52 @@
+// Keeps the tab and status bars in tabs opened from generated layouts
+const DEFAULT_TAB_TEMPLATE: &str = r#"    default_tab_template {
+        pane size=1 borderless=true {
+            plugin location="zellij:tab-bar"
+        }
+        children
+        pane size=2 borderless=true {
+            plugin location="zellij:status-bar"
+        }
+    }
+"#;
+
+// Escapes a value to be used as a KDL string
+pub(crate) fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len() + 2);
+    escaped.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+// Runs the commands and keeps an interactive shell open, so that further steps can be pasted into the pane
+pub(crate) fn keep_shell(commands: &str) -> String {
+    format!("{}\nexec \"${{SHELL:-sh}}\"", commands)
+}
+
+// Builds a layout with a single tab holding a named pane per (name, commands) pair, each running its commands
+pub(crate) fn command_tab(tab_name: &str, panes: &[(String, String)]) -> String {
+    let mut layout = format!(
+        "layout {{\n{}    tab name={} focus=true {{\n",
+        DEFAULT_TAB_TEMPLATE,
+        escape(tab_name)
+    );
+    for (pane_name, commands) in panes {
+        layout.push_str(&format!(
+            "        pane name={} command=\"sh\" {{\n            args \"-c\" {}\n        }}\n",
+            escape(pane_name),
+            escape(&keep_shell(commands)),
+        ));
+    }
+    layout.push_str("    }\n}\n");
+    layout
+}
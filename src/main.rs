@@ -1,62 +1,135 @@
+mod checks;
 mod file_picker;
 mod filters;
+mod front_matter;
+mod guards;
+mod highlight;
+mod history;
 mod keybindings;
+mod layout;
+mod panes;
+mod pipe;
+mod playbook;
+mod prompt;
 mod render;
+mod shell_history;
+mod structured;
 mod tab_manager;
+mod task_files;
+mod theme;
+mod variables;
 
+use crate::checks::{CheckResult, CheckStatus};
 use crate::file_picker::{FileItem, FilePicker};
-use crate::filters::{FileFilter, Filter, PlaybookFilter};
+use crate::filters::{FileFilter, Filter, HistoryFilter, PaneFilter, PlaybookFilter};
+use crate::front_matter::FrontMatter;
+use crate::guards::{Guard, HostFacts};
+use crate::highlight::Token;
+use crate::history::{History, HistoryEntry, Status};
 use crate::keybindings::{Keybinding, Keybindings};
+use crate::panes::{PaneItem, Panes};
+use crate::pipe::{
+    Action, PasteQueue, PipeReply, PipeRequest, QueuedText, PIPE_PRESENCE_NAME, PIPE_REPLY_NAME,
+};
+use crate::playbook::{PlaybookLine, Target};
+use crate::prompt::{Prompt, PromptKind, StepAction};
 use crate::tab_manager::TabManager;
+use crate::theme::Theme;
+use crate::variables::Variable;
 
 use num_enum::{IntoPrimitive, TryFromPrimitive};
-use owo_colors::OwoColorize;
-use serde::{Deserialize, Serialize};
 use std::cmp::PartialEq;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::fmt;
 use std::fmt::{Debug, Formatter};
 use std::io::Read;
+use std::path::Component;
+use std::str::FromStr;
 use std::{fs, path};
 use zellij_tile::prelude::*;
 
 const CONFIGURATION_IGNORE_COMMENTS: &str = "ignore_comments";
 const CONFIGURATION_SORT_FILES: &str = "sort_files";
 const CONFIGURATION_PIPE_MODE: &str = "pipe_mode";
+const CONFIGURATION_SHELL_HISTORY: &str = "shell_history";
+const CONFIGURATION_HIDE_SKIPPED_STEPS: &str = "hide_skipped_steps";
+const CONFIGURATION_TRUNCATE_MIDDLE: &str = "truncate_middle";
+const CONFIGURATION_PROMPT_VARIABLES: &str = "prompt_variables";
+const CONFIGURATION_PIPE_DELAY_MS: &str = "pipe_delay_ms";
+const CONFIGURATION_PIPE_DROP_DUPLICATES: &str = "pipe_drop_duplicates";
 
 const CWD: &str = "/host";
+// Shared by the instances of the plugin
+const DATA_DIR: &str = "/data";
 
-const BASE_COLOR: usize = 2;
+const CONTEXT_TARGET_PANE: &str = "target_pane";
+const CONTEXT_PIPE_ID: &str = "pipe_id";
+const CONTEXT_PLUGIN_ID: &str = "plugin_id";
+const CONTEXT_HISTORY_ID: &str = "history_id";
+const CONTEXT_SHELL_HISTORY: &str = "shell_history";
+const CONTEXT_HOST_FACTS: &str = "host_facts";
+const CONTEXT_CHECK_STEP: &str = "check_step";
 
 const RESERVE_ROW_COUNT: usize = 6;
 
-#[derive(Default, Debug, Serialize, Deserialize, Clone)]
-struct PlaybookLine {
-    id: usize,
-    content: String,
-}
-
-impl PlaybookLine {
-    fn new(id: usize, content: String) -> Self {
-        Self { id, content }
-    }
-}
-
 #[derive(Debug, Clone)]
 struct State {
     mode: Mode,
     pipe_mode: bool,
+    // A pipe mode instance announced itself, it takes the texts to paste and run
+    pipe_mode_running: bool,
+    pipe_delay_ms: u64,
+    pipe_drop_duplicates: bool,
+    paste_queue: PasteQueue,
+    history: History,
+    history_mgr: TabManager<HistoryEntry>,
+    // Playbook the last history entry was saved to
+    history_file: Option<String>,
     ignore_comments: bool,
     sort_files: bool,
+    // Off by default: history files hold tokens and passwords typed on the command line,
+    // which would otherwise show up in every session where the plugin is opened
+    shell_history: bool,
+    // Playbook mode lists the shell history instead of a file
+    showing_shell_history: bool,
     filter_mode: filters::Mode,
     filter: String,
     files_mgr: TabManager<FileItem>,
     playbook_mgr: TabManager<PlaybookLine>,
+    panes_mgr: TabManager<PaneItem>,
     file_picker: FilePicker,
+    panes: Panes,
+    marked_panes: BTreeSet<u32>,
+    prompt: Option<Prompt>,
     keybindings: Keybindings,
+    theme: Theme,
     crit_error_message: Option<String>,
     error_message: Option<String>,
+    info_message: Option<String>,
     current_file: Option<String>,
+    // Position in the history log where the recording of a macro started
+    recording: Option<usize>,
+    // Last values given to step variables, suggested the next time
+    variables: BTreeMap<String, String>,
+    // Steps of the playbook whose sub-steps are listed
+    expanded: BTreeSet<usize>,
+    // Settings of the header of the current playbook
+    front_matter: Option<FrontMatter>,
+    // Whether a step was sent with a `cd` into the working directory of the front matter
+    entered_cwd: bool,
+    // Host name, OS and environment the step guards are evaluated against, read once needed
+    host_facts: Option<HostFacts>,
+    host_facts_requested: bool,
+    // Hide steps with a false guard instead of showing them greyed out
+    hide_skipped_steps: bool,
+    // Cut long rows in the middle instead of at the end, to keep the end of paths visible
+    truncate_middle: bool,
+    // Ask for the `{{name}}` placeholders of the steps of any playbook
+    prompt_variables: bool,
+    // Outcomes of the steps of the playbook checked in the background, by step id
+    checks: BTreeMap<usize, CheckResult>,
+    // Show the details of the selected step below the list
+    show_details: bool,
 }
 
 impl Default for State {
@@ -64,17 +137,44 @@ impl Default for State {
         Self {
             mode: Default::default(),
             pipe_mode: false,
+            pipe_mode_running: false,
+            pipe_delay_ms: 0,
+            pipe_drop_duplicates: false,
+            paste_queue: Default::default(),
+            history: Default::default(),
+            history_mgr: TabManager::new(Vec::new()),
+            history_file: None,
             ignore_comments: true,
             sort_files: true,
+            shell_history: false,
+            showing_shell_history: false,
             filter_mode: Default::default(),
             filter: "".to_string(),
             files_mgr: Default::default(),
             playbook_mgr: Default::default(),
+            panes_mgr: Default::default(),
             file_picker: Default::default(),
+            panes: Default::default(),
+            marked_panes: BTreeSet::new(),
+            prompt: None,
             keybindings: Default::default(),
+            theme: Default::default(),
             crit_error_message: None,
             error_message: None,
+            info_message: None,
             current_file: None,
+            recording: None,
+            variables: BTreeMap::new(),
+            expanded: BTreeSet::new(),
+            front_matter: None,
+            entered_cwd: false,
+            host_facts: None,
+            host_facts_requested: false,
+            hide_skipped_steps: false,
+            truncate_middle: false,
+            prompt_variables: false,
+            checks: BTreeMap::new(),
+            show_details: false,
         }
     }
 }
@@ -86,6 +186,8 @@ enum Mode {
     FilePicker = 1,
     Playbook = 2,
     Usage = 3,
+    Panes = 4,
+    History = 5,
 }
 
 trait Navigation {
@@ -102,11 +204,25 @@ impl Navigation for Mode {
 
     fn prev(&self) -> Mode {
         let prev = (*self as u32).saturating_sub(1);
-        Mode::try_from(prev).unwrap_or(Mode::Usage)
+        Mode::try_from(prev).unwrap_or(Mode::History)
     }
 
     fn iter() -> impl Iterator<Item = Self> {
-        (1..=3).filter_map(|v| Mode::try_from(v).ok())
+        (1..=5).filter_map(|v| Mode::try_from(v).ok())
+    }
+}
+
+impl FromStr for Mode {
+    type Err = String;
+
+    // Accepts a mode name, case insensitive, or its number
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(number) = s.parse::<u32>() {
+            return Mode::try_from(number).map_err(|_| format!("unknown mode '{}'", s));
+        }
+        Mode::iter()
+            .find(|m| m.to_string().eq_ignore_ascii_case(s))
+            .ok_or(format!("unknown mode '{}'", s))
     }
 }
 
@@ -116,32 +232,626 @@ impl fmt::Display for Mode {
             Self::FilePicker => "FilePicker",
             Self::Playbook => "Playbook",
             Self::Usage => "Usage",
+            Self::Panes => "Panes",
+            Self::History => "History",
         };
         write!(f, "{}", name)
     }
 }
 
+// Steps of task files and structured playbooks are not lines of the file, they can't be saved or appended
+fn is_plain_playbook(file_path: &str) -> bool {
+    !task_files::is_task_file(file_path) && !structured::is_structured(file_path)
+}
+
 impl State {
     fn playbook_filter(&self) -> Box<dyn Filter<PlaybookLine>> {
-        Box::new(PlaybookFilter::new(self.filter_mode, self.filter.clone()))
+        Box::new(PlaybookFilter::new(
+            self.filter_mode,
+            self.filter.clone(),
+            self.expanded.clone(),
+        ))
     }
 
     fn file_filter(&self) -> Box<dyn Filter<crate::file_picker::FileItem>> {
         Box::new(FileFilter::new(self.filter_mode, self.filter.clone()))
     }
 
+    fn pane_filter(&self) -> Box<dyn Filter<PaneItem>> {
+        Box::new(PaneFilter::new(self.filter_mode, self.filter.clone()))
+    }
+
+    fn history_filter(&self) -> Box<dyn Filter<HistoryEntry>> {
+        Box::new(HistoryFilter::new(self.filter_mode, self.filter.clone()))
+    }
+
+    // A filter typed in another mode would silently pick the broadcast targets, Panes mode starts without one
+    fn switch_mode(&mut self, mode: Mode) {
+        self.mode = mode;
+        self.filter_mode = filters::Mode::default();
+        if mode == Mode::Panes {
+            self.filter.clear();
+        }
+        self.set_filter();
+    }
+
     fn set_filter(&mut self) {
         match self.mode {
             Mode::FilePicker => self.files_mgr.with_filter(self.file_filter()),
             Mode::Playbook => self.playbook_mgr.with_filter(self.playbook_filter()),
+            Mode::Panes => self.panes_mgr.with_filter(self.pane_filter()),
+            Mode::History => self.refresh_history(),
             _ => {}
         }
     }
 
+    // Rebuilds the pane list keeping the selected pane, titles change while commands run
+    fn refresh_panes(&mut self) {
+        self.marked_panes
+            .retain(|pane_id| self.panes.get(*pane_id).is_some());
+        let selected = self.panes_mgr.get_selected().map(|p| p.pane_id);
+        self.panes_mgr = self.panes.manager();
+        self.panes_mgr.with_filter(self.pane_filter());
+        if let Some(id) = selected {
+            self.panes_mgr.select_by(|p| p.pane_id == id);
+        }
+    }
+
+    fn toggle_mark(&mut self) {
+        if let Some(pane) = self.panes_mgr.get_selected() {
+            if !self.marked_panes.remove(&pane.pane_id) {
+                self.marked_panes.insert(pane.pane_id);
+            }
+        }
+    }
+
+    // Sends the selected step to the marked panes, or to every pane matched by the filter if nothing is marked
+    fn broadcast(&mut self) {
+        let Some(line) = self.playbook_mgr.get_selected().cloned() else {
+            self.handle_error("No step selected, pick one in Playbook mode first.".to_string());
+            return;
+        };
+        self.ask_variables(line, StepAction::Broadcast);
+    }
+
+    fn broadcast_step(&mut self, line: &PlaybookLine) {
+        let targets: Vec<PaneItem> = if !self.marked_panes.is_empty() {
+            self.marked_panes
+                .iter()
+                .filter_map(|pane_id| self.panes.get(*pane_id).cloned())
+                .collect()
+        } else if !self.filter.is_empty() {
+            self.panes_mgr.iter().map(|(_, p)| p.clone()).collect()
+        } else {
+            self.handle_error(format!(
+                "Mark panes with {} or filter them by tab or title to choose broadcast targets.",
+                self.keybindings.mark
+            ));
+            return;
+        };
+
+        if targets.is_empty() {
+            self.handle_error("No panes match the broadcast targets.".to_string());
+            return;
+        }
+
+        for pane in &targets {
+            write_chars_to_pane_id(
+                &format!("{}\n", line.content),
+                PaneId::Terminal(pane.pane_id),
+            );
+            let mut entry = HistoryEntry::new(
+                history::BROADCAST_SOURCE.to_string(),
+                Action::Paste,
+                line.clone(),
+                true,
+            );
+            entry.file = self.current_file.clone();
+            let history_id = self.push_history(entry);
+            self.finish_history(history_id, Ok(format!("pane {}", pane.label)));
+        }
+
+        let names: Vec<&str> = targets.iter().map(|p| p.label.as_str()).collect();
+        // Broadcasts go to the chosen panes only
+        let ignored = line
+            .target
+            .as_ref()
+            .map(|t| format!(" The step's '{}' target was ignored.", t))
+            .unwrap_or_default();
+        self.handle_info(format!(
+            "Sent step {} to {} pane(s): {}.{}",
+            line.id,
+            targets.len(),
+            names.join(", "),
+            ignored
+        ));
+    }
+
     fn get_cwd(&self) -> path::PathBuf {
         path::PathBuf::from(CWD)
     }
 
+    // Only files inside the current directory can be written
+    fn resolve_file(&self, file_path: &str) -> Result<path::PathBuf, Box<dyn std::error::Error>> {
+        let relative = path::Path::new(file_path);
+        if file_path.is_empty()
+            || relative
+                .components()
+                .any(|c| !matches!(c, Component::Normal(_)))
+        {
+            return Err(format!("'{}' is not a file in the current directory", file_path).into());
+        }
+        Ok(self.get_cwd().join(relative))
+    }
+
+    // Editors send absolute paths, which are made relative to the directory of the plugin
+    fn relative_file(&self, file_path: &str) -> Result<String, String> {
+        let path = path::Path::new(file_path);
+        if !path.is_absolute() {
+            return Ok(file_path.to_string());
+        }
+        let initial_cwd = get_plugin_ids().initial_cwd;
+        path.strip_prefix(&initial_cwd)
+            .map(|p| p.to_string_lossy().to_string())
+            .map_err(|_| {
+                format!(
+                    "'{}' is outside of the plugin directory '{}'",
+                    file_path,
+                    initial_cwd.display()
+                )
+            })
+    }
+
+    fn append_step(&mut self, file_path: &str, content: &str) -> Result<(), String> {
+        if content.trim().is_empty() {
+            return Ok(());
+        }
+        if !is_plain_playbook(file_path) {
+            return Err(format!(
+                "Steps can't be appended to '{}', edit the file instead",
+                file_path
+            ));
+        }
+
+        self.resolve_file(file_path)
+            .and_then(|path| playbook::append_lines(&path, content))
+            .map_err(|e| format!("Failed to append to '{}': {}", file_path, e))?;
+
+        if self.current_file.as_deref() == Some(file_path) {
+            self.refresh_playbook();
+        } else if !self.pipe_mode {
+            self.handle_info(format!("Appended to '{}'.", file_path));
+        }
+        Ok(())
+    }
+
+    // Loads the file unless it's already the current playbook, and shows it in Playbook mode
+    fn open_playbook(&mut self, file_path: &str) -> Result<(), String> {
+        if self.current_file.as_deref() != Some(file_path) {
+            self.load_file(file_path)
+                .map_err(|e| format!("Failed to load file '{}': {}", file_path, e))?;
+        }
+        self.mode = Mode::Playbook;
+        self.filter_mode = filters::Mode::default();
+        self.filter = "".to_string();
+        self.set_filter();
+        Ok(())
+    }
+
+    // The step to deliver for a pipe request: the payload, or a step of the given playbook
+    fn pipe_step(&self, request: &PipeRequest) -> Result<PlaybookLine, String> {
+        let mut line = match (&request.payload, request.step, &request.file) {
+            (Some(payload), _, _) => PlaybookLine::new(0, payload.clone()),
+            (None, Some(id), Some(file)) => {
+                let path = self.resolve_file(file).map_err(|e| e.to_string())?;
+                let content = fs::read_to_string(&path)
+                    .map_err(|e| format!("Failed to load file '{}': {}", file, e))?;
+                let mut line = self
+                    .parse_file(file, &content)?
+                    .0
+                    .into_iter()
+                    .find(|l| l.id == id)
+                    .ok_or(format!("Step {} not found in '{}'", id, file))?;
+
+                // Nobody is asked before a piped step is sent, so its guards and its
+                // confirmation are checked here
+                guards::evaluate(
+                    std::slice::from_mut(&mut line),
+                    self.host_facts.as_ref(),
+                    &self.get_cwd(),
+                );
+                if let Some(reason) = &line.skipped {
+                    return Err(format!("Step {} is skipped, {}", id, reason));
+                }
+                if line.confirm && !request.force {
+                    return Err(format!(
+                        "Step {} asks for confirmation, pass '{}=true' to send it",
+                        id,
+                        pipe::ARG_FORCE
+                    ));
+                }
+                line
+            }
+            _ => return Err("Nothing to send".to_string()),
+        };
+
+        if let Some(target) = &request.target {
+            line.target = Some(Target::Pane(target.clone()));
+        }
+        Ok(line)
+    }
+
+    // Resolves the step of the request and records it in the history, failures included
+    fn record_step(
+        &mut self,
+        request: &PipeRequest,
+        source: &PipeSource,
+    ) -> Result<(usize, PlaybookLine), String> {
+        let line = self.pipe_step(request);
+        let recorded = match &line {
+            Ok(line) => line.clone(),
+            Err(_) => PlaybookLine::new(
+                0,
+                request
+                    .payload
+                    .clone()
+                    .unwrap_or_else(|| format!("step {}", request.step.unwrap_or_default())),
+            ),
+        };
+        let mut entry = HistoryEntry::new(
+            history::source_label(source),
+            request.action,
+            recorded,
+            request.newline,
+        );
+        if request.payload.is_none() {
+            entry.file = request.file.clone().or(self.current_file.clone());
+        }
+        let history_id = self.push_history(entry);
+
+        match line {
+            Ok(line) => Ok((history_id, line)),
+            Err(e) => {
+                self.finish_history(history_id, Err(e.clone()));
+                Err(e)
+            }
+        }
+    }
+
+    fn push_history(&mut self, entry: HistoryEntry) -> usize {
+        let (history_id, result) = self.history.push(entry);
+        if let Err(e) = result {
+            self.handle_error(format!("Failed to write the history log: {}", e));
+        }
+        history_id
+    }
+
+    fn finish_history(&mut self, history_id: usize, status: impl Into<Status>) {
+        if let Err(e) = self.history.finish(history_id, status.into()) {
+            self.handle_error(format!("Failed to write the history log: {}", e));
+        }
+    }
+
+    // Runs the step in a new command pane, named after its pane target
+    fn run_step(&self, line: PlaybookLine, mut context: BTreeMap<String, String>) {
+        if let Some(Target::Pane(name)) = &line.target {
+            context.insert(CONTEXT_TARGET_PANE.to_string(), name.clone());
+        }
+        open_command_pane(self.command_to_run(line), context);
+    }
+
+    // Runs the step with its working directory, relative to the plugin directory, and its environment
+    fn command_to_run(&self, line: PlaybookLine) -> CommandToRun {
+        let mut script = line.content;
+        let mut cwd = None;
+        match line.cwd {
+            // The home directory is only known to the shell
+            Some(dir) if dir.starts_with('~') => {
+                script = format!("cd {} && {}", playbook::shell_quote_dir(&dir), script);
+            }
+            Some(dir) => cwd = Some(get_plugin_ids().initial_cwd.join(dir)),
+            None => {}
+        }
+
+        let mut command = CommandToRun::new_with_args("sh", vec!["-c".to_string(), script]);
+        if !line.env.is_empty() {
+            // `env` sets the variables for the shell
+            let mut args: Vec<String> = line
+                .env
+                .iter()
+                .map(|(name, value)| format!("{}={}", name, value))
+                .collect();
+            args.push(command.path.to_string_lossy().to_string());
+            args.append(&mut command.args);
+            command = CommandToRun::new_with_args("env", args);
+        }
+        command.cwd = cwd;
+        command
+    }
+
+    // Sends a history entry again, recorded as a new entry
+    fn resend(&mut self, entry: HistoryEntry) {
+        let mut resent = HistoryEntry::new(
+            format!("re-send {}", entry.id),
+            entry.action,
+            entry.line.clone(),
+            entry.newline,
+        );
+        resent.file = entry.file.clone();
+        let history_id = self.push_history(resent);
+        match entry.action {
+            Action::Paste | Action::Insert => {
+                let item = QueuedText {
+                    action: entry.action,
+                    line: entry.line,
+                    newline: entry.newline,
+                    source: PipeSource::Keybind,
+                    history_id,
+                };
+                self.paste_queue.push(item, false);
+                self.process_queue();
+            }
+            Action::Run => {
+                let context =
+                    BTreeMap::from([(CONTEXT_HISTORY_ID.to_string(), history_id.to_string())]);
+                self.run_step(entry.line, context);
+            }
+            _ => self.finish_history(
+                history_id,
+                Err(format!("'{}' entries can't be re-sent", entry.action)),
+            ),
+        }
+        self.refresh_history();
+    }
+
+    // Rebuilds the history list keeping the selected entry.
+    // The pipe mode instance lists its own entries, History mode lists the log of all instances.
+    fn refresh_history(&mut self) {
+        let selected = self.history_mgr.get_selected().map(|e| e.id);
+        if self.pipe_mode {
+            self.history_mgr = self.history.manager();
+        } else {
+            match self.history.load_log() {
+                Ok(entries) => self.history_mgr = TabManager::new(entries),
+                Err(e) => self.handle_error(format!("Failed to load the history log: {}", e)),
+            }
+            self.history_mgr.with_filter(self.history_filter());
+        }
+        if let Some(id) = selected {
+            self.history_mgr.select_by(|e| e.id == id);
+        }
+    }
+
+    // Returns None if the reply is sent later, once the command has finished
+    fn handle_pipe_request(
+        &mut self,
+        request: PipeRequest,
+        source: &PipeSource,
+    ) -> Result<Option<PipeReply>, String> {
+        let mut reply = PipeReply::ok(request.action);
+        match request.action {
+            Action::Paste | Action::Insert => {
+                let (history_id, line) = self.record_step(&request, source)?;
+                let item = QueuedText {
+                    action: request.action,
+                    line,
+                    newline: request.newline,
+                    source: source.clone(),
+                    history_id,
+                };
+                if !self.paste_queue.push(item, self.pipe_drop_duplicates) {
+                    self.finish_history(history_id, Status::Dropped);
+                    reply.coalesced = true;
+                    return Ok(Some(reply));
+                }
+                // The caller waits for its turn in the queue
+                if let PipeSource::Cli(pipe_id) = source {
+                    block_cli_pipe_input(pipe_id);
+                }
+                self.process_queue();
+                return Ok(None);
+            }
+            Action::Run => {
+                let (history_id, line) = self.record_step(&request, source)?;
+                let mut context = BTreeMap::new();
+                context.insert(CONTEXT_HISTORY_ID.to_string(), history_id.to_string());
+                // The caller waits for the exit code
+                match source {
+                    PipeSource::Cli(pipe_id) => {
+                        block_cli_pipe_input(pipe_id);
+                        context.insert(CONTEXT_PIPE_ID.to_string(), pipe_id.clone());
+                    }
+                    PipeSource::Plugin(plugin_id) => {
+                        context.insert(CONTEXT_PLUGIN_ID.to_string(), plugin_id.to_string());
+                    }
+                    PipeSource::Keybind => {}
+                }
+                self.run_step(line, context);
+                if *source != PipeSource::Keybind {
+                    return Ok(None);
+                }
+            }
+            Action::Append => {
+                let file = request.file.unwrap_or_default();
+                let payload = request.payload.unwrap_or_default();
+                let mut entry = HistoryEntry::new(
+                    history::source_label(source),
+                    request.action,
+                    PlaybookLine::new(0, payload.clone()),
+                    true,
+                );
+                entry.file = Some(file.clone());
+                let history_id = self.push_history(entry);
+                let result = self.append_step(&file, &payload);
+                self.finish_history(history_id, result.clone().map(|_| format!("'{}'", file)));
+                result?;
+                reply.file = Some(file);
+            }
+            Action::Load => {
+                let file = request.file.unwrap_or_default();
+                self.resolve_file(&file).map_err(|e| e.to_string())?;
+                self.open_playbook(&file)?;
+                reply.file = Some(file);
+            }
+            Action::Select => {
+                let file = request.file.or(self.current_file.clone()).ok_or(format!(
+                    "'{}' argument is required, no playbook is loaded",
+                    pipe::ARG_FILE
+                ))?;
+                self.resolve_file(&file).map_err(|e| e.to_string())?;
+                self.open_playbook(&file)?;
+
+                let id = request.step.unwrap_or_default();
+                if !self.playbook_mgr.iter().any(|(_, l)| l.id == id) {
+                    return Err(format!("Step {} not found", id));
+                }
+                self.playbook_mgr.select_by(|l| l.id == id);
+                reply.file = Some(file);
+                reply.step = Some(id);
+            }
+            Action::Open => {
+                let file = self.relative_file(&request.file.unwrap_or_default())?;
+                self.resolve_file(&file).map_err(|e| e.to_string())?;
+                // The editor may have saved the file right before
+                if self.current_file.as_deref() == Some(file.as_str()) {
+                    self.refresh_playbook();
+                }
+                self.open_playbook(&file)?;
+
+                let (first, last) = request.lines.unwrap_or_default();
+                let id = playbook::step_at(self.playbook_mgr.origin(), first, last)
+                    .map(|l| l.id)
+                    .ok_or(format!("Playbook '{}' has no steps", file))?;
+                self.playbook_mgr.select_by(|l| l.id == id);
+                reply.file = Some(file);
+                reply.step = Some(id);
+            }
+            Action::Mode => self.switch_mode(request.mode.unwrap_or_default()),
+            Action::ListFiles => {
+                self.file_picker
+                    .load_files(&self.get_cwd(), self.sort_files)
+                    .map_err(|e| format!("Failed to load files: {}", e))?;
+                reply.files = Some(
+                    self.file_picker
+                        .manager()
+                        .origin()
+                        .filter(|f| !f.is_shell_history)
+                        .map(|f| f.name.clone())
+                        .collect(),
+                );
+            }
+            Action::ListSteps => {
+                let file = request.file.unwrap_or_default();
+                let path = self.resolve_file(&file).map_err(|e| e.to_string())?;
+                let content = fs::read_to_string(&path)
+                    .map_err(|e| format!("Failed to load file '{}': {}", file, e))?;
+                reply.steps = Some(self.parse_file(&file, &content)?.0);
+                reply.file = Some(file);
+            }
+        }
+        Ok(Some(reply))
+    }
+
+    // Delivers queued texts in order, pausing between them if a delay is configured
+    fn process_queue(&mut self) {
+        while let Some(item) = self.paste_queue.pop() {
+            let text = item.line.wrapped_content();
+            let result = self.deliver(&text, item.line.target.as_ref(), item.newline);
+            self.finish_history(item.history_id, result.clone());
+            let reply = match result {
+                Ok(pane) => PipeReply {
+                    pane: Some(pane),
+                    ..PipeReply::ok(item.action)
+                },
+                Err(e) => PipeReply::error(Some(item.action), e),
+            };
+            self.reply(&item.source, reply);
+
+            if self.pipe_delay_ms > 0 {
+                self.paste_queue.wait();
+                set_timeout(self.pipe_delay_ms as f64 / 1000.0);
+                break;
+            }
+        }
+    }
+
+    // Each instance announces its role once permitted. The pipe mode instance answers the
+    // interactive ones started after it, so that they know whether texts are taken care of.
+    fn handle_presence(&mut self, pipe_message: &PipeMessage) {
+        let PipeSource::Plugin(plugin_id) = pipe_message.source else {
+            return;
+        };
+        if plugin_id == get_plugin_ids().plugin_id {
+            return;
+        }
+        if pipe::is_pipe_mode_role(pipe_message) {
+            self.pipe_mode_running = true;
+        } else if self.pipe_mode {
+            pipe::announce(true, Some(plugin_id));
+        }
+    }
+
+    fn reply(&mut self, source: &PipeSource, reply: PipeReply) {
+        match source {
+            PipeSource::Cli(pipe_id) => {
+                cli_pipe_output(pipe_id, &format!("{}\n", reply.to_json()));
+                unblock_cli_pipe_input(pipe_id);
+            }
+            PipeSource::Plugin(plugin_id) => {
+                pipe_message_to_plugin(
+                    MessageToPlugin::new(PIPE_REPLY_NAME)
+                        .with_destination_plugin_id(*plugin_id)
+                        .with_payload(reply.to_json()),
+                );
+            }
+            PipeSource::Keybind => {}
+        }
+        if let Some(e) = reply.error {
+            self.handle_error(e);
+        }
+    }
+
+    // Task runner files list their targets, structured playbooks their steps, other files their lines
+    fn parse_file(
+        &self,
+        file_path: &str,
+        content: &str,
+    ) -> Result<(Vec<PlaybookLine>, Option<FrontMatter>), String> {
+        let lines = match task_files::parse(file_path, content) {
+            Ok(None) => structured::parse(file_path, content),
+            lines => lines,
+        };
+        match lines {
+            Ok(Some(lines)) => Ok((lines, None)),
+            Ok(None) => front_matter::split(path::Path::new(file_path), content)
+                .map(|(front_matter, content)| self.parse_playbook(front_matter, &content))
+                .map_err(|e| format!("Failed to parse '{}': {}", file_path, e)),
+            Err(e) => Err(format!("Failed to parse '{}': {}", file_path, e)),
+        }
+    }
+
+    // Lines of a plain playbook, with the settings of its front matter applied
+    fn parse_playbook(
+        &self,
+        front_matter: Option<FrontMatter>,
+        content: &str,
+    ) -> (Vec<PlaybookLine>, Option<FrontMatter>) {
+        let ignore_comments = front_matter
+            .as_ref()
+            .and_then(|f| f.ignore_comments)
+            .unwrap_or(self.ignore_comments);
+
+        let sections = front_matter
+            .as_ref()
+            .and_then(|f| f.sections)
+            .unwrap_or(ignore_comments);
+        let mut lines = playbook::parse(content, ignore_comments, sections);
+        if let Some(front_matter) = &front_matter {
+            lines.iter_mut().for_each(|l| front_matter.apply(l));
+        }
+        (lines, front_matter)
+    }
+
     fn load_file(&mut self, file_path: &str) -> Result<(), Box<dyn std::error::Error>> {
         let path = self.get_cwd().join(file_path);
 
@@ -149,52 +859,707 @@ impl State {
         let mut content = String::new();
         file.read_to_string(&mut content)?;
 
-        let lines: Vec<String> = content.lines().map(|s| s.to_string()).collect();
-        let mut playbook_lines = Vec::new();
-        let mut id = 1;
-
-        for line in lines {
-            // Skip empty lines
-            if line.trim().is_empty() {
-                continue;
-            }
-
-            // Skip comments if ignore_comments is enabled
-            if self.ignore_comments && line.trim().starts_with('#') {
-                continue;
-            }
+        let (mut playbook_lines, front_matter) = self.parse_file(file_path, &content)?;
 
-            playbook_lines.push(PlaybookLine::new(id, line));
-            id += 1;
+        let needs_host = playbook_lines
+            .iter()
+            .flat_map(|l| &l.guards)
+            .any(Guard::needs_host);
+        if needs_host && !self.host_facts_requested {
+            self.load_host_facts();
+        }
+        guards::evaluate(
+            &mut playbook_lines,
+            self.host_facts.as_ref(),
+            &self.get_cwd(),
+        );
+        if self.hide_skipped_steps {
+            playbook_lines.retain(|l| l.skipped.is_none());
         }
 
+        if self.current_file.as_deref() != Some(file_path) {
+            self.entered_cwd = false;
+            self.checks.clear();
+        } else {
+            // A check only stands for the command it ran, results of edited steps are dropped
+            let previous = &self.playbook_mgr;
+            self.checks.retain(|id, _| {
+                let before = previous.origin().find(|l| l.id == *id);
+                let after = playbook_lines.iter().find(|l| l.id == *id);
+                matches!((before, after), (Some(b), Some(a)) if b.content == a.content)
+            });
+        }
+        self.front_matter = front_matter;
         self.playbook_mgr = TabManager::new(playbook_lines);
+        self.expanded.clear();
         self.current_file = Some(file_path.to_string());
+        self.showing_shell_history = false;
 
         Ok(())
     }
 
+    // The playbook is evaluated again once the facts are read
+    fn load_host_facts(&mut self) {
+        self.host_facts_requested = true;
+        let context = BTreeMap::from([(CONTEXT_HOST_FACTS.to_string(), String::new())]);
+        run_command(&["sh", "-c", guards::FACTS_SCRIPT], context);
+    }
+
+    // The history is read by a command, files outside of the plugin directory can't be opened
+    fn load_shell_history(&self) {
+        let script = shell_history::read_script();
+        let context = BTreeMap::from([(CONTEXT_SHELL_HISTORY.to_string(), String::new())]);
+        run_command(&["sh", "-c", &script], context);
+    }
+
+    fn show_shell_history(&mut self, output: &[u8]) {
+        let lines = shell_history::parse(&String::from_utf8_lossy(output));
+        if lines.is_empty() {
+            self.handle_error(
+                "No shell history found in ~/.bash_history, ~/.zsh_history or the fish history."
+                    .to_string(),
+            );
+            return;
+        }
+
+        self.playbook_mgr = TabManager::new(lines);
+        self.current_file = None;
+        self.front_matter = None;
+        self.showing_shell_history = true;
+        self.mode = Mode::Playbook;
+        self.filter_mode = filters::Mode::default();
+        self.filter = "".to_string();
+        self.set_filter();
+    }
+
     fn handle_error(&mut self, error_message: String) {
         self.error_message = Some(error_message.clone());
         eprintln!("Error: {}", error_message);
     }
 
-    fn render_errors(&mut self) -> bool {
+    // Opens a command pane running the script, named so that further steps can target it
+    fn open_step_pane(&self, script: String, name: Option<&str>) {
+        let command = CommandToRun::new_with_args("sh", vec!["-c".to_string(), script]);
+        let mut context = BTreeMap::new();
+        if let Some(name) = name {
+            context.insert(CONTEXT_TARGET_PANE.to_string(), name.to_string());
+        }
+        open_command_pane(command, context);
+    }
+
+    // Writes the text into its target pane, or into the previous pane if there is no target.
+    // Missing targets are created to run the text unless it's inserted without a newline.
+    // Returns a description of the pane that received the text.
+    fn deliver(
+        &mut self,
+        text: &str,
+        target: Option<&Target>,
+        newline: bool,
+    ) -> Result<String, String> {
+        let chars = if newline {
+            format!("{}\n", text)
+        } else {
+            text.to_string()
+        };
+
+        match target {
+            None => {
+                focus_previous_pane();
+                write_chars(&chars);
+                focus_previous_pane();
+                Ok("previous pane".to_string())
+            }
+            Some(Target::Pane(name)) => match self.panes.find_by_title(name) {
+                Some(pane) => {
+                    write_chars_to_pane_id(&chars, PaneId::Terminal(pane.pane_id));
+                    Ok(format!("pane '{}'", pane.title))
+                }
+                None if newline => {
+                    self.open_step_pane(layout::keep_shell(text), Some(name));
+                    Ok(format!("new pane '{}'", name))
+                }
+                None => Err(format!("No pane matches '{}'", name)),
+            },
+            Some(Target::Tab(name)) => match self.panes.find_in_tab(name) {
+                Some(pane_id) => {
+                    write_chars_to_pane_id(&chars, PaneId::Terminal(pane_id));
+                    Ok(format!("tab '{}'", name))
+                }
+                None if newline => {
+                    new_tabs_with_layout(&layout::command_tab(
+                        name,
+                        &[(name.clone(), text.to_string())],
+                    ));
+                    Ok(format!("new tab '{}'", name))
+                }
+                None => Err(format!("No tab named '{}'", name)),
+            },
+        }
+    }
+
+    // Shows or hides the sub-steps of the selected step, or of the step of the selected sub-step
+    fn toggle_expanded(&mut self) {
+        let Some(line) = self.playbook_mgr.get_selected() else {
+            return;
+        };
+        let id = line.parent.unwrap_or(line.id);
+        if !self.playbook_mgr.origin().any(|l| l.parent == Some(id)) {
+            return;
+        }
+
+        if !self.expanded.remove(&id) {
+            self.expanded.insert(id);
+        }
+        self.set_filter();
+        self.playbook_mgr.select_by(|l| l.id == id);
+    }
+
+    // Asks for the values of the step variables one by one, then sends or broadcasts the step
+    fn ask_variables(&mut self, line: PlaybookLine, action: StepAction) {
+        if let Some(reason) = &line.skipped {
+            self.handle_error(format!(
+                "Step {} is skipped, {}. Edit it with {} to send it anyway.",
+                line.id, reason, self.keybindings.edit_step
+            ));
+            return;
+        }
+        if line.confirm {
+            self.prompt = Some(Prompt::new(PromptKind::Confirm { line, action }, ""));
+            return;
+        }
+        let pending = self.step_variables(&line);
+        self.continue_variables(line, action, pending, BTreeMap::new());
+    }
+
+    // Placeholders are filled in for the recipes of task files and the steps that declare `vars`,
+    // or for all steps with `prompt_variables`. Other steps are sent as they are, such as Go templates.
+    fn step_variables(&self, line: &PlaybookLine) -> Vec<Variable> {
+        let task_file = self
+            .current_file
+            .as_deref()
+            .is_some_and(task_files::is_task_file);
+        if self.prompt_variables || task_file || !line.vars.is_empty() {
+            variables::find(&line.content)
+        } else {
+            Vec::new()
+        }
+    }
+
+    fn continue_variables(
+        &mut self,
+        line: PlaybookLine,
+        action: StepAction,
+        pending: Vec<Variable>,
+        values: BTreeMap<String, String>,
+    ) {
+        let Some(variable) = pending.first() else {
+            let mut line = PlaybookLine {
+                content: variables::substitute(&line.content, &values),
+                ..line
+            };
+            // Recorded as sent, a re-send must not wrap it again
+            line.content = line.wrapped_content();
+            line.cwd = None;
+            line.env.clear();
+            if let Some(cwd) = self.front_matter.as_ref().and_then(|f| f.cwd.as_ref()) {
+                // Checks run in a new shell each time
+                if !self.entered_cwd || action == StepAction::Check {
+                    line.content =
+                        format!("cd {} && {}", playbook::shell_quote_dir(cwd), line.content);
+                    self.entered_cwd |= action != StepAction::Check;
+                }
+            }
+            match action {
+                StepAction::Send => self.send_step(&line),
+                StepAction::Broadcast => self.broadcast_step(&line),
+                StepAction::Check => self.check_step(&line),
+            }
+            return;
+        };
+
+        let text = self.suggested_value(&line, variable);
+        let kind = PromptKind::Variable {
+            line,
+            action,
+            pending,
+            values,
+        };
+        self.prompt = Some(Prompt::new(kind, &text));
+    }
+
+    fn suggested_value(&self, line: &PlaybookLine, variable: &Variable) -> String {
+        self.known_value(line, variable).unwrap_or_default()
+    }
+
+    // The value last entered for the variable, or else the one of the step, or else its default
+    fn known_value(&self, line: &PlaybookLine, variable: &Variable) -> Option<String> {
+        self.variables
+            .get(&variable.name)
+            .or(line.vars.get(&variable.name))
+            .or(variable.default.as_ref())
+            .cloned()
+    }
+
+    // Fills in the variables of a step that runs without prompts, all of them must have a value
+    fn fill_variables(&self, line: &PlaybookLine) -> Result<PlaybookLine, String> {
+        let mut values = BTreeMap::new();
+        for variable in self.step_variables(line) {
+            let value = self.known_value(line, &variable).ok_or(format!(
+                "Step {} has no value for '{}', send it once to fill it in.",
+                line.id, variable.name
+            ))?;
+            values.insert(variable.name, value);
+        }
+        Ok(PlaybookLine {
+            content: variables::substitute(&line.content, &values),
+            ..line.clone()
+        })
+    }
+
+    // Pastes the step into its target pane, or into the previous pane if it has no target
+    fn send_step(&mut self, line: &PlaybookLine) {
+        let mut entry = HistoryEntry::new(
+            history::PLAYBOOK_SOURCE.to_string(),
+            Action::Paste,
+            line.clone(),
+            true,
+        );
+        entry.file = self.current_file.clone();
+        let history_id = self.push_history(entry);
+
+        let result = self.deliver(&line.content, line.target.as_ref(), true);
+        self.finish_history(history_id, result.clone());
+        if let Err(e) = result {
+            self.handle_error(format!("Failed to send step {}: {}", line.id, e));
+        }
+    }
+
+    // Runs the step in the background, from the plugin directory, to compare its outcome with its expectations
+    fn check_step(&mut self, line: &PlaybookLine) {
+        let mut entry = HistoryEntry::new(
+            history::CHECK_SOURCE.to_string(),
+            Action::Run,
+            line.clone(),
+            true,
+        );
+        entry.file = self.current_file.clone();
+        let history_id = self.push_history(entry);
+
+        let context = BTreeMap::from([
+            (CONTEXT_CHECK_STEP.to_string(), line.id.to_string()),
+            (CONTEXT_HISTORY_ID.to_string(), history_id.to_string()),
+        ]);
+        run_command_with_env_variables_and_cwd(
+            &["sh", "-c", &line.content],
+            BTreeMap::new(),
+            get_plugin_ids().initial_cwd,
+            context,
+        );
+        self.checks.insert(line.id, CheckResult::running());
+    }
+
+    fn finish_check(
+        &mut self,
+        context: &BTreeMap<String, String>,
+        exit_code: Option<i32>,
+        stdout: &[u8],
+        stderr: &[u8],
+    ) {
+        let Some(id) = context
+            .get(CONTEXT_CHECK_STEP)
+            .and_then(|id| id.parse::<usize>().ok())
+        else {
+            return;
+        };
+        let history_id = context
+            .get(CONTEXT_HISTORY_ID)
+            .and_then(|id| id.parse::<usize>().ok());
+        if let Some(history_id) = history_id {
+            self.history.set_exit_code(history_id, exit_code);
+        }
+
+        // The playbook was changed in the meantime, the result doesn't stand for the step anymore
+        let line = self
+            .playbook_mgr
+            .origin()
+            .find(|l| l.id == id && self.checks.contains_key(&id))
+            .cloned();
+        let Some(line) = line else {
+            if let Some(history_id) = history_id {
+                self.finish_history(
+                    history_id,
+                    Err("check discarded, the step was changed".to_string()),
+                );
+            }
+            return;
+        };
+
+        let result = checks::evaluate(&line, exit_code, stdout, stderr);
+        if let Some(history_id) = history_id {
+            let status = match &result.status {
+                CheckStatus::Failed(reason) => Err(format!("check failed, {}", reason)),
+                _ => Ok("background check".to_string()),
+            };
+            self.finish_history(history_id, status);
+        }
+        self.checks.insert(id, result);
+    }
+
+    // Reloads the current playbook keeping the filter and the selected step
+    fn refresh_playbook(&mut self) {
+        let Some(file) = self.current_file.clone() else {
+            return;
+        };
+        let selected = self.playbook_mgr.get_selected().map(|l| l.id);
+
+        if let Err(e) = self.load_file(&file) {
+            self.handle_error(format!("Failed to reload file: {}", e));
+            return;
+        }
+
+        if self.mode == Mode::Playbook {
+            self.playbook_mgr.with_filter(self.playbook_filter());
+        }
+        if let Some(id) = selected {
+            self.playbook_mgr.select_by(|l| l.id == id);
+        }
+    }
+
+    // Only the files at the top of the plugin directory are listed, hidden files such as
+    // temporary files of atomic writes are left out
+    fn is_listed_path(&self, path: &path::Path) -> bool {
+        let relative = path
+            .strip_prefix(self.get_cwd())
+            .or_else(|_| path.strip_prefix(get_plugin_ids().initial_cwd))
+            .unwrap_or(path);
+        let mut components = relative.components();
+        match (components.next(), components.next()) {
+            (Some(Component::Normal(name)), None) => {
+                name.to_str().is_some_and(|n| !n.starts_with('.'))
+            }
+            _ => false,
+        }
+    }
+
+    fn is_current_file_changed(&self, paths: &[(path::PathBuf, Option<FileMetadata>)]) -> bool {
+        let Some(file) = self.current_file.as_ref() else {
+            return false;
+        };
+        paths.iter().any(|(p, _)| p.ends_with(file))
+    }
+
+    fn handle_prompt_key(&mut self, key: KeyWithModifier) {
+        let Some(mut prompt) = self.prompt.take() else {
+            return;
+        };
+
+        match key.bare_key {
+            BareKey::Esc => {}
+            BareKey::Enter => self.submit_prompt(prompt),
+            _ if self.keybindings.save_step.matches(&key) => match prompt.kind {
+                PromptKind::EditStep(line) => self.save_step(&line, prompt.editor.text()),
+                _ => self.prompt = Some(prompt),
+            },
+            _ => {
+                prompt.editor.handle_key(&key);
+                self.prompt = Some(prompt);
+            }
+        }
+    }
+
+    fn submit_prompt(&mut self, prompt: Prompt) {
+        let text = prompt.editor.text();
+        match prompt.kind {
+            PromptKind::EditStep(line) => {
+                // The edited step is sent as is, even if it's skipped, the playbook stays untouched
+                let edited = PlaybookLine {
+                    content: text,
+                    skipped: None,
+                    ..line
+                };
+                self.ask_variables(edited, StepAction::Send);
+            }
+            PromptKind::AppendStep(file) => {
+                if let Err(e) = self.append_step(&file, &text) {
+                    self.handle_error(e);
+                }
+            }
+            PromptKind::Export => self.export_history(text.trim()),
+            PromptKind::Confirm { line, action } => {
+                let pending = self.step_variables(&line);
+                self.continue_variables(line, action, pending, BTreeMap::new());
+            }
+            PromptKind::Variable {
+                line,
+                action,
+                mut pending,
+                mut values,
+            } => {
+                if !pending.is_empty() {
+                    let variable = pending.remove(0);
+                    self.variables.insert(variable.name.clone(), text.clone());
+                    values.insert(variable.name, text);
+                }
+                self.continue_variables(line, action, pending, values);
+            }
+            PromptKind::SaveMacro(since) => self.save_macro(since, text.trim()),
+            PromptKind::SaveEntry(line) => {
+                let file = text.trim().to_string();
+                match self.append_step(&file, &line.content) {
+                    Ok(()) => {
+                        self.handle_info(format!("Saved to '{}'.", file));
+                        self.history_file = Some(file);
+                    }
+                    Err(e) => self.handle_error(e),
+                }
+            }
+        }
+    }
+
+    // Starts recording the sent steps, or asks where to save them.
+    // The recording goes on until it is saved or discarded with an empty file name.
+    fn toggle_recording(&mut self) {
+        match self.recording {
+            None => match self.history.load_log() {
+                Ok(entries) => self.recording = Some(entries.len()),
+                Err(e) => self.handle_error(format!("Failed to start recording: {}", e)),
+            },
+            Some(since) => {
+                let file = format!(
+                    "macro-{}.playbook",
+                    history::format_file_stamp(history::now())
+                );
+                self.prompt = Some(Prompt::new(PromptKind::SaveMacro(since), &file));
+            }
+        }
+    }
+
+    // Writes the steps delivered since the recording started as a new playbook
+    fn save_macro(&mut self, since: usize, file_path: &str) {
+        if file_path.is_empty() {
+            self.recording = None;
+            self.handle_info("Discarded the recording.".to_string());
+            return;
+        }
+        let (steps, undelivered) = match self.history.load_log() {
+            Ok(entries) => (
+                history::recorded_steps(&entries, since),
+                history::undelivered_count(&entries, since),
+            ),
+            Err(e) => {
+                self.handle_error(format!("Failed to load the history log: {}", e));
+                return;
+            }
+        };
+        // Texts that failed or are still running are left out of the macro, and said so
+        let left_out = if undelivered > 0 {
+            format!(
+                " {} step(s) that failed or are still running were left out.",
+                undelivered
+            )
+        } else {
+            String::new()
+        };
+        if steps.is_empty() {
+            self.recording = None;
+            self.handle_info(format!(
+                "No steps reached a pane while recording.{}",
+                left_out
+            ));
+            return;
+        }
+
+        let result = self.resolve_file(file_path).and_then(|path| {
+            if path.exists() {
+                return Err("the file already exists".into());
+            }
+            playbook::write_atomic(&path, &playbook::render(&steps))
+        });
+        match result {
+            Ok(()) => {
+                self.recording = None;
+                if let Err(e) = self
+                    .file_picker
+                    .load_files(&self.get_cwd(), self.sort_files)
+                {
+                    self.handle_error(format!("Failed to reload files: {}", e));
+                }
+                self.files_mgr = self.file_picker.manager();
+                if self.mode == Mode::FilePicker {
+                    self.files_mgr.with_filter(self.file_filter());
+                }
+                self.handle_info(format!(
+                    "Saved {} recorded step(s) to '{}'.{}",
+                    steps.len(),
+                    file_path,
+                    left_out
+                ));
+            }
+            Err(e) => self.handle_error(format!(
+                "Failed to save the recorded steps to '{}', the recording goes on: {}",
+                file_path, e
+            )),
+        }
+    }
+
+    // Writes the entries listed in History mode as a shell script for `.sh` files, as Markdown otherwise
+    fn export_history(&mut self, file_path: &str) {
+        let entries: Vec<HistoryEntry> = self.history_mgr.iter().map(|(_, e)| e.clone()).collect();
+        let shell = file_path.ends_with(".sh");
+
+        let result = self.resolve_file(file_path).and_then(|path| {
+            if path.exists() {
+                return Err("the file already exists".into());
+            }
+            playbook::write_atomic(&path, &history::transcript(&entries, shell))
+        });
+        match result {
+            Ok(()) => self.handle_info(format!(
+                "Exported {} step(s) to '{}'.",
+                entries.len(),
+                file_path
+            )),
+            Err(e) => self.handle_error(format!(
+                "Failed to export the history to '{}': {}",
+                file_path, e
+            )),
+        }
+    }
+
+    // Keys of the pipe history shown by the pipe mode instance
+    fn handle_history_key(&mut self, key: KeyWithModifier) -> bool {
+        if self.prompt.is_some() {
+            self.handle_prompt_key(key);
+            return true;
+        }
+
+        match key.bare_key {
+            // The instance keeps serving pipes while hidden
+            BareKey::Esc => hide_self(),
+            BareKey::Down | BareKey::Tab => self.history_mgr.select_down(),
+            BareKey::Up => self.history_mgr.select_up(),
+            BareKey::Enter => {
+                if let Some(entry) = self.history_mgr.get_selected().cloned() {
+                    self.resend(entry);
+                }
+            }
+            _ if self.keybindings.append.matches(&key) => {
+                if let Some(entry) = self.history_mgr.get_selected() {
+                    let file = self.history_file.clone().unwrap_or_default();
+                    self.prompt = Some(Prompt::new(
+                        PromptKind::SaveEntry(entry.line.clone()),
+                        &file,
+                    ));
+                }
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    fn save_step(&mut self, line: &PlaybookLine, content: String) {
+        let Some(file) = self.current_file.clone() else {
+            self.handle_error("No playbook file to save to.".to_string());
+            return;
+        };
+        if !is_plain_playbook(&file) {
+            self.handle_error(format!(
+                "Steps of '{}' can't be saved, edit the file instead.",
+                file
+            ));
+            return;
+        }
+
+        let path = self.get_cwd().join(&file);
+        match playbook::replace_line(&path, line.line_number, &line.content, &content) {
+            Ok(()) => self.refresh_playbook(),
+            Err(e) => self.handle_error(format!(
+                "Failed to save step {} to '{}': {}",
+                line.id, file, e
+            )),
+        }
+    }
+
+    // Panes run their steps without asking: skipped steps and sub-steps are left out,
+    // steps to confirm are refused and variables must already have a value
+    fn layout_steps(&self) -> Result<Vec<PlaybookLine>, String> {
+        self.playbook_mgr
+            .origin()
+            .filter(|l| l.skipped.is_none() && l.parent.is_none())
+            .map(|line| {
+                if line.confirm {
+                    return Err(format!(
+                        "Step {} asks for confirmation, send it from the list instead of a layout.",
+                        line.id
+                    ));
+                }
+                self.fill_variables(line)
+            })
+            .collect()
+    }
+
+    // Opens the current playbook as a new tab with a command pane per section
+    fn open_as_layout(&mut self) {
+        let Some(file) = self.current_file.clone() else {
+            self.handle_error(
+                "No playbook loaded, select one in FilePicker mode first.".to_string(),
+            );
+            return;
+        };
+
+        if !is_plain_playbook(&file) {
+            self.handle_error(format!(
+                "'{}' lists targets or structured steps, only the sections of a playbook can be opened as a layout.",
+                file
+            ));
+            return;
+        }
+
+        let steps = match self.layout_steps() {
+            Ok(steps) => steps,
+            Err(e) => {
+                self.handle_error(e);
+                return;
+            }
+        };
+        let panes: Vec<(String, String)> = playbook::sections(steps.iter(), &file)
+            .into_iter()
+            .map(|(name, steps)| {
+                let commands: Vec<String> = steps.iter().map(|l| l.wrapped_content()).collect();
+                (name, commands.join("\n"))
+            })
+            .collect();
+
+        if panes.is_empty() {
+            self.handle_error(format!("Playbook '{}' has no steps.", file));
+            return;
+        }
+
+        new_tabs_with_layout(&layout::command_tab(&file, &panes));
+    }
+
+    fn handle_info(&mut self, info_message: String) {
+        self.info_message = Some(info_message);
+    }
+
+    fn render_messages(&mut self) -> bool {
         if let Some(e) = self.crit_error_message.as_ref() {
-            let text = Text::new(format!("ERROR: {}", e.red()));
-            print_text_with_coordinates(text, 1, 1, None, None);
+            render::render_message(&self.theme, e, true);
             return true;
         }
         if let Some(e) = self.error_message.take() {
-            let text = Text::new(format!("ERROR: {}", e.red()));
-            print_text_with_coordinates(text, 1, 1, None, None);
+            render::render_message(&self.theme, &e, true);
+            return true;
+        }
+        if let Some(i) = self.info_message.take() {
+            render::render_message(&self.theme, &i, false);
             return true;
         }
         false
     }
 
     fn render_usage(&self) {
-        render::render_mode(0, 0, Mode::Usage);
+        render::render_mode(&self.theme, 0, 0, Mode::Usage);
 
         let mut table = Table::new();
 
@@ -213,8 +1578,15 @@ impl State {
         ]);
         table = table.add_row(vec![
             format!("{}|{} {}", BareKey::Tab, BareKey::Down, BareKey::Up).as_str(),
-            "Navigate through the list of files or lines.",
-            format!("{}|{}", Mode::FilePicker, Mode::Playbook).as_str(),
+            "Navigate through the list of files, lines, panes or sent steps.",
+            format!(
+                "{}|{}|{}|{}",
+                Mode::FilePicker,
+                Mode::Playbook,
+                Mode::Panes,
+                Mode::History
+            )
+            .as_str(),
             "False",
         ]);
         table = table.add_row(vec![
@@ -226,13 +1598,32 @@ impl State {
         table = table.add_row(vec![
             BareKey::Backspace.to_string().as_str(),
             "Remove the last character from the filter.",
-            format!("{}|{}", Mode::FilePicker, Mode::Playbook).as_str(),
+            format!(
+                "{}|{}|{}|{}",
+                Mode::FilePicker,
+                Mode::Playbook,
+                Mode::Panes,
+                Mode::History
+            )
+            .as_str(),
             "False",
         ]);
         table = table.add_row(vec![
             BareKey::Enter.to_string().as_str(),
             "Select file or paste the selected line into the terminal.",
-            "*",
+            format!("{}|{}", Mode::FilePicker, Mode::Playbook).as_str(),
+            "False",
+        ]);
+        table = table.add_row(vec![
+            BareKey::Enter.to_string().as_str(),
+            "Send the selected line to the marked panes or to all filtered panes.",
+            Mode::Panes.to_string().as_str(),
+            "False",
+        ]);
+        table = table.add_row(vec![
+            BareKey::Enter.to_string().as_str(),
+            "Send the selected step again.",
+            Mode::History.to_string().as_str(),
             "False",
         ]);
         table = table.add_row(vec![
@@ -242,14 +1633,26 @@ impl State {
             "False",
         ]);
         table = table.add_row(vec![
-            format!("{:?} {}", KeyModifier::Ctrl, Mode::Playbook as u32).as_str(),
-            "Switch to Playbook mode.",
+            format!("{:?} {}", KeyModifier::Ctrl, Mode::Playbook as u32).as_str(),
+            "Switch to Playbook mode.",
+            "*",
+            "False",
+        ]);
+        table = table.add_row(vec![
+            format!("{:?} {}", KeyModifier::Ctrl, Mode::Usage as u32).as_str(),
+            "Switch to Usage mode to view instructions.",
+            "*",
+            "False",
+        ]);
+        table = table.add_row(vec![
+            format!("{:?} {}", KeyModifier::Ctrl, Mode::Panes as u32).as_str(),
+            "Switch to Panes mode to broadcast the selected line.",
             "*",
             "False",
         ]);
         table = table.add_row(vec![
-            format!("{:?} {}", KeyModifier::Ctrl, Mode::Usage as u32).as_str(),
-            "Switch to Usage mode to view instructions.",
+            format!("{:?} {}", KeyModifier::Ctrl, Mode::History as u32).as_str(),
+            "Switch to History mode to browse the steps sent in this session.",
             "*",
             "False",
         ]);
@@ -270,16 +1673,139 @@ impl State {
         table = table.add_row(vec![
             self.keybindings.switch_filter_id.to_string().as_str(),
             "Switch to id filtering mode.",
+            format!(
+                "{}|{}|{}|{}",
+                Mode::FilePicker,
+                Mode::Playbook,
+                Mode::Panes,
+                Mode::History
+            )
+            .as_str(),
+            "True",
+        ]);
+        table = table.add_row(vec![
+            self.keybindings.edit_step.to_string().as_str(),
+            "Edit the selected line before sending it, Enter sends and Esc cancels.",
+            Mode::Playbook.to_string().as_str(),
+            "True",
+        ]);
+        table = table.add_row(vec![
+            self.keybindings.save_step.to_string().as_str(),
+            "Save the edited line back to the playbook file.",
+            Mode::Playbook.to_string().as_str(),
+            "True",
+        ]);
+        table = table.add_row(vec![
+            self.keybindings.append.to_string().as_str(),
+            "Append a typed line to the current or the selected playbook.",
             format!("{}|{}", Mode::FilePicker, Mode::Playbook).as_str(),
             "True",
         ]);
+        table = table.add_row(vec![
+            self.keybindings.check.to_string().as_str(),
+            "Run the selected line in the background and check its exit code and output.",
+            Mode::Playbook.to_string().as_str(),
+            "True",
+        ]);
+        table = table.add_row(vec![
+            self.keybindings.details.to_string().as_str(),
+            "Show or hide the full command and the details of the selected line.",
+            Mode::Playbook.to_string().as_str(),
+            "True",
+        ]);
+        table = table.add_row(vec![
+            self.keybindings.expand.to_string().as_str(),
+            "Show or hide the commands of the selected task as sub-steps.",
+            Mode::Playbook.to_string().as_str(),
+            "True",
+        ]);
+        table = table.add_row(vec![
+            self.keybindings.layout.to_string().as_str(),
+            "Open the playbook as a new tab with a command pane per section.",
+            Mode::Playbook.to_string().as_str(),
+            "True",
+        ]);
+        table = table.add_row(vec![
+            self.keybindings.mark.to_string().as_str(),
+            "Mark or unmark the selected pane as a broadcast target.",
+            Mode::Panes.to_string().as_str(),
+            "True",
+        ]);
+        table = table.add_row(vec![
+            self.keybindings.record.to_string().as_str(),
+            "Start recording the sent steps, press again to save them as a new playbook.",
+            "*",
+            "True",
+        ]);
+        table = table.add_row(vec![
+            self.keybindings.export.to_string().as_str(),
+            "Export the listed steps as a Markdown or, for .sh files, a shell script transcript.",
+            Mode::History.to_string().as_str(),
+            "True",
+        ]);
 
         print_table_with_coordinates(table, 2, 2, None, None);
     }
 
+    fn render_pipe_history(&self, rows: usize, cols: usize) {
+        let labels: Vec<(usize, usize, String)> = self
+            .history_mgr
+            .iter()
+            .map(|(i, e)| (i, e.id, e.label()))
+            .collect();
+        let iter = labels.iter().map(|(i, id, label)| (*i, *id, label));
+        let hint = format!(
+            "{} re-send, {} save to playbook, {} hide",
+            BareKey::Enter,
+            self.keybindings.append,
+            BareKey::Esc
+        );
+        render::render_pipe_history(
+            &self.theme,
+            rows,
+            cols,
+            self.history_mgr.get_position(),
+            self.history_mgr.len(),
+            hint,
+            &[],
+            &render::Highlights::new(),
+            self.truncate_middle,
+            iter,
+        );
+    }
+
+    fn render_history(&self, rows: usize, cols: usize) {
+        let labels: Vec<(usize, usize, String)> = self
+            .history_mgr
+            .iter()
+            .map(|(i, e)| (i, e.id, e.label()))
+            .collect();
+        let iter = labels.iter().map(|(i, id, label)| (*i, *id, label));
+        render::render_main_menu(
+            &self.theme,
+            rows,
+            cols,
+            self.history_mgr.get_position(),
+            self.history_mgr.len(),
+            Mode::History,
+            self.filter.clone(),
+            self.filter_mode.to_string(),
+            &[],
+            &render::Highlights::new(),
+            self.truncate_middle,
+            iter,
+        );
+    }
+
     fn render_file_picker(&self, rows: usize, cols: usize) {
-        let iter = self.files_mgr.iter().map(|(i, f)| (i, f.id, &f.name));
+        let labels: Vec<(usize, usize, String)> = self
+            .files_mgr
+            .iter()
+            .map(|(i, f)| (i, f.id, f.label().to_string()))
+            .collect();
+        let iter = labels.iter().map(|(i, id, label)| (*i, *id, label));
         render::render_main_menu(
+            &self.theme,
             rows,
             cols,
             self.files_mgr.get_position(),
@@ -287,20 +1813,200 @@ impl State {
             Mode::FilePicker,
             self.filter.clone(),
             self.filter_mode.to_string(),
+            &[],
+            &render::Highlights::new(),
+            self.truncate_middle,
             iter,
         );
     }
 
     fn render_playbook(&self, rows: usize, cols: usize) {
-        let iter = self.playbook_mgr.iter().map(|(i, l)| (i, l.id, &l.content));
+        let mut highlights = render::Highlights::new();
+        let labels: Vec<(usize, usize, String)> = self
+            .playbook_mgr
+            .iter()
+            .map(|(i, l)| {
+                let mut label = l.label();
+                let mut tokens = l.label_tokens();
+                if !l.tags.is_empty() {
+                    let begin = label.chars().count() + 2;
+                    let tags: Vec<String> = l.tags.iter().map(|t| format!("#{}", t)).collect();
+                    label.push_str(&format!("  {}", tags.join(" ")));
+                    tokens.push((Token::Tag, begin..label.chars().count()));
+                }
+                if let Some(reason) = &l.skipped {
+                    label.push_str(&format!("  [skipped, {}]", reason));
+                }
+                if let Some(check) = self.checks.get(&l.id) {
+                    label.push_str(&format!("  [{}]", check.label()));
+                }
+                highlights.insert(l.id, tokens);
+                (i, l.id, label)
+            })
+            .collect();
+        let skipped: Vec<usize> = self
+            .playbook_mgr
+            .iter()
+            .filter(|(_, l)| l.skipped.is_some())
+            .map(|(_, l)| l.id)
+            .collect();
+        // The details of the selected step, or the outcome of its check, take the bottom of the pane
+        let selected = self.playbook_mgr.get_selected();
+        let check = selected.and_then(|l| self.checks.get(&l.id));
+        let width = cols.saturating_sub(2);
+        let panel = match (selected, check) {
+            (Some(line), _) if self.show_details => {
+                let max_height = rows / 2;
+                let mut lines = self.step_details(line, width);
+                if let Some(check) = check {
+                    let output = render::wrap(&check.output, width);
+                    let room = max_height.saturating_sub(lines.len() + 1);
+                    lines.extend_from_slice(&output[output.len().saturating_sub(room)..]);
+                }
+                Some((format!("Step {}", line.id), lines, max_height))
+            }
+            (_, Some(check)) => {
+                let output: Vec<String> = check.output.lines().map(String::from).collect();
+                Some((check.summary(), output, rows / 3))
+            }
+            _ => None,
+        };
+        let panel_height = panel.as_ref().map_or(0, |(_, lines, max_height)| {
+            (lines.len() + 1).min(*max_height)
+        });
+
+        let iter = labels.iter().map(|(i, id, label)| (*i, *id, label));
         render::render_main_menu(
-            rows,
+            &self.theme,
+            rows.saturating_sub(panel_height),
             cols,
             self.playbook_mgr.get_position(),
             self.playbook_mgr.len(),
             Mode::Playbook,
             self.filter.clone(),
             self.filter_mode.to_string(),
+            &skipped,
+            &highlights,
+            self.truncate_middle,
+            iter,
+        );
+
+        if let Some(front_matter) = &self.front_matter {
+            let header = match (&front_matter.title, &front_matter.description) {
+                (Some(title), Some(description)) => format!("{}: {}", title, description),
+                (Some(text), None) | (None, Some(text)) => text.clone(),
+                (None, None) => String::new(),
+            };
+            render::render_header(&self.theme, 2, 1, cols, header);
+        }
+
+        if let Some((title, lines, _)) = panel {
+            // Only the end of the output fits
+            let begin = if self.show_details {
+                0
+            } else {
+                lines.len().saturating_sub(panel_height.saturating_sub(1))
+            };
+            render::render_panel(
+                &self.theme,
+                2,
+                rows.saturating_sub(panel_height) + 1,
+                cols,
+                panel_height,
+                title,
+                &lines[begin..],
+            );
+        } else if let Some(line) = selected.filter(|l| l.name.is_some()) {
+            render::render_detail(&self.theme, 2, rows.saturating_sub(2), cols, &line.content);
+        }
+    }
+
+    // The full command of the step and everything known about it, wrapped to the width
+    fn step_details(&self, line: &PlaybookLine, width: usize) -> Vec<String> {
+        let mut fields = Vec::new();
+        if let Some(name) = &line.name {
+            fields.push(format!("Name: {}", name));
+        }
+        fields.push(format!("$ {}", line.content));
+        if let Some(description) = &line.description {
+            fields.push(description.clone());
+        }
+        let variables: Vec<String> = self
+            .step_variables(line)
+            .iter()
+            .map(|v| format!("{}={}", v.name, self.suggested_value(line, v)))
+            .collect();
+        if !variables.is_empty() {
+            fields.push(format!("Variables: {}", variables.join(", ")));
+        }
+        if let Some(file) = &self.current_file {
+            fields.push(format!("Source: {}:{}", file, line.line_number));
+        }
+        if !line.tags.is_empty() {
+            fields.push(format!("Tags: {}", line.tags.join(", ")));
+        }
+        if let Some(target) = &line.target {
+            fields.push(format!("Target: {}", target));
+        }
+        if let Some(cwd) = &line.cwd {
+            fields.push(format!("Directory: {}", cwd));
+        }
+        if !line.env.is_empty() {
+            let env: Vec<String> = line
+                .env
+                .iter()
+                .map(|(k, v)| format!("{}={}", k, v))
+                .collect();
+            fields.push(format!("Environment: {}", env.join(" ")));
+        }
+        if !line.guards.is_empty() {
+            let guards: Vec<String> = line.guards.iter().map(|g| g.to_string()).collect();
+            let state = match &line.skipped {
+                Some(reason) => format!(", skipped: {}", reason),
+                None => String::new(),
+            };
+            fields.push(format!("Guards: {}{}", guards.join(", "), state));
+        }
+        let last_run = self
+            .current_file
+            .as_ref()
+            .and_then(|f| self.history.last_of_step(f, line.id));
+        if let Some(entry) = last_run {
+            fields.push(format!("Last run: {}", entry.outcome()));
+        }
+        if let Some(check) = self.checks.get(&line.id) {
+            fields.push(format!("Check: {}", check.summary()));
+        }
+
+        fields.iter().flat_map(|f| render::wrap(f, width)).collect()
+    }
+
+    fn render_panes(&self, rows: usize, cols: usize) {
+        let labels: Vec<(usize, usize, String)> = self
+            .panes_mgr
+            .iter()
+            .map(|(i, p)| {
+                let mark = if self.marked_panes.contains(&p.pane_id) {
+                    "[x]"
+                } else {
+                    "[ ]"
+                };
+                (i, p.id, format!("{} {}", mark, p.label))
+            })
+            .collect();
+        let iter = labels.iter().map(|(i, id, label)| (*i, *id, label));
+        render::render_main_menu(
+            &self.theme,
+            rows,
+            cols,
+            self.panes_mgr.get_position(),
+            self.panes_mgr.len(),
+            Mode::Panes,
+            self.filter.clone(),
+            self.filter_mode.to_string(),
+            &[],
+            &render::Highlights::new(),
+            self.truncate_middle,
             iter,
         );
     }
@@ -313,6 +2019,9 @@ impl ZellijPlugin for State {
             PermissionType::ChangeApplicationState,
             PermissionType::WriteToStdin,
             PermissionType::OpenFiles,
+            PermissionType::RunCommands,
+            PermissionType::ReadCliPipes,
+            PermissionType::MessageAndLaunchOtherPlugins,
         ]);
 
         if let Some(value) = configuration.get(CONFIGURATION_PIPE_MODE) {
@@ -324,10 +2033,6 @@ impl ZellijPlugin for State {
             })
         }
 
-        if self.pipe_mode {
-            return;
-        }
-
         if let Some(value) = configuration.get(CONFIGURATION_IGNORE_COMMENTS) {
             self.ignore_comments = value.trim().parse::<bool>().unwrap_or_else(|_| {
                 self.handle_error(
@@ -337,16 +2042,35 @@ impl ZellijPlugin for State {
             })
         }
 
-        if let Some(value) = configuration.get(CONFIGURATION_SORT_FILES) {
-            self.sort_files = value.trim().parse::<bool>().unwrap_or_else(|_| {
+        if let Some(value) = configuration.get(CONFIGURATION_PIPE_DELAY_MS) {
+            self.pipe_delay_ms = value.trim().parse::<u64>().unwrap_or_else(|_| {
                 self.handle_error(
-                    format!("'{CONFIGURATION_SORT_FILES}' config value must be 'true' or 'false', but it's '{value}'. The true is used.")
+                    format!("'{CONFIGURATION_PIPE_DELAY_MS}' config value must be a number of milliseconds, but it's '{value}'. The 0 is used.")
                 );
-                true
+                0
+            })
+        }
+
+        if let Some(value) = configuration.get(CONFIGURATION_PIPE_DROP_DUPLICATES) {
+            self.pipe_drop_duplicates = value.trim().parse::<bool>().unwrap_or_else(|_| {
+                self.handle_error(
+                    format!("'{CONFIGURATION_PIPE_DROP_DUPLICATES}' config value must be 'true' or 'false', but it's '{value}'. The false is used.")
+                );
+                false
             })
         }
 
-        match Keybindings::new(configuration) {
+        // Every instance of the session logs to the same file
+        let plugin_ids = get_plugin_ids();
+        self.history = History::with_log(
+            path::PathBuf::from(format!(
+                "{}/history-{}.jsonl",
+                DATA_DIR, plugin_ids.zellij_pid
+            )),
+            plugin_ids.plugin_id,
+        );
+
+        match Keybindings::new(configuration.clone()) {
             Ok(kb) => self.keybindings = kb,
             Err(e) => {
                 self.handle_error(format!(
@@ -355,6 +2079,80 @@ impl ZellijPlugin for State {
             }
         }
 
+        match Theme::new(configuration.clone()) {
+            Ok(theme) => self.theme = theme,
+            Err(e) => {
+                self.handle_error(format!(
+                    "Failed to parse zellij-playbooks theme, check your config: {}. Default is used.",
+                    e
+                ));
+            }
+        }
+
+        if let Some(value) = configuration.get(CONFIGURATION_SHELL_HISTORY) {
+            self.shell_history = value.trim().parse::<bool>().unwrap_or_else(|_| {
+                self.handle_error(
+                    format!("'{CONFIGURATION_SHELL_HISTORY}' config value must be 'true' or 'false', but it's '{value}'. The false is used.")
+                );
+                false
+            })
+        }
+        self.file_picker.shell_history = self.shell_history;
+
+        if let Some(value) = configuration.get(CONFIGURATION_HIDE_SKIPPED_STEPS) {
+            self.hide_skipped_steps = value.trim().parse::<bool>().unwrap_or_else(|_| {
+                self.handle_error(
+                    format!("'{CONFIGURATION_HIDE_SKIPPED_STEPS}' config value must be 'true' or 'false', but it's '{value}'. The false is used.")
+                );
+                false
+            })
+        }
+
+        if let Some(value) = configuration.get(CONFIGURATION_PROMPT_VARIABLES) {
+            self.prompt_variables = value.trim().parse::<bool>().unwrap_or_else(|_| {
+                self.handle_error(
+                    format!("'{CONFIGURATION_PROMPT_VARIABLES}' config value must be 'true' or 'false', but it's '{value}'. The false is used.")
+                );
+                false
+            })
+        }
+
+        if let Some(value) = configuration.get(CONFIGURATION_TRUNCATE_MIDDLE) {
+            self.truncate_middle = value.trim().parse::<bool>().unwrap_or_else(|_| {
+                self.handle_error(
+                    format!("'{CONFIGURATION_TRUNCATE_MIDDLE}' config value must be 'true' or 'false', but it's '{value}'. The false is used.")
+                );
+                false
+            })
+        }
+
+        if let Some(value) = configuration.get(CONFIGURATION_SORT_FILES) {
+            self.sort_files = value.trim().parse::<bool>().unwrap_or_else(|_| {
+                self.handle_error(
+                    format!("'{CONFIGURATION_SORT_FILES}' config value must be 'true' or 'false', but it's '{value}'. The true is used.")
+                );
+                true
+            })
+        }
+
+        // The settings above also apply to the pipe mode instance, which lists files and steps
+        if self.pipe_mode {
+            // Panes are tracked to resolve pipe targets, timers pace the paste queue,
+            // keys drive the history shown when the instance is visible, and command
+            // results bring the host facts the guards of piped steps are checked against
+            subscribe(&[
+                EventType::Key,
+                EventType::TabUpdate,
+                EventType::PaneUpdate,
+                EventType::CommandPaneOpened,
+                EventType::CommandPaneExited,
+                EventType::Timer,
+                EventType::PermissionRequestResult,
+                EventType::RunCommandResult,
+            ]);
+            return;
+        }
+
         // Load files from current directory
         if let Err(e) = self
             .file_picker
@@ -364,17 +2162,160 @@ impl ZellijPlugin for State {
         }
         self.files_mgr = self.file_picker.manager();
 
-        subscribe(&[EventType::Key]);
+        subscribe(&[
+            EventType::Key,
+            EventType::TabUpdate,
+            EventType::PaneUpdate,
+            EventType::CommandPaneOpened,
+            EventType::CommandPaneExited,
+            EventType::Timer,
+            EventType::PermissionRequestResult,
+            EventType::RunCommandResult,
+            EventType::FileSystemCreate,
+            EventType::FileSystemUpdate,
+            EventType::FileSystemDelete,
+        ]);
+        watch_filesystem();
     }
 
     fn update(&mut self, event: Event) -> bool {
+        let mut should_render = false;
+
+        let panes_changed = match &event {
+            Event::TabUpdate(tabs) => self.panes.update_tabs(tabs.clone()),
+            Event::PaneUpdate(manifest) => self.panes.update_manifest(manifest.clone()),
+            _ => false,
+        };
+        if panes_changed {
+            self.refresh_panes();
+            should_render = self.mode == Mode::Panes;
+        }
+
+        if let Event::PermissionRequestResult(PermissionStatus::Granted) = event {
+            pipe::announce(self.pipe_mode, None);
+            // Steps requested through the pipe are checked against their guards
+            if !self.host_facts_requested {
+                self.load_host_facts();
+            }
+        }
+
+        // Name panes created for `@pane` targets, so that further steps find them
+        if let Event::CommandPaneOpened(pane_id, context) = &event {
+            if let Some(name) = context.get(CONTEXT_TARGET_PANE) {
+                rename_terminal_pane(*pane_id, name);
+            }
+        }
+
+        // Answer `run` requests once their command has finished
+        if let Event::CommandPaneExited(pane_id, exit_code, context) = &event {
+            let pane = context
+                .get(CONTEXT_TARGET_PANE)
+                .map(|name| format!("pane '{}'", name))
+                .unwrap_or_else(|| format!("pane {}", pane_id));
+
+            if let Some(history_id) = context
+                .get(CONTEXT_HISTORY_ID)
+                .and_then(|id| id.parse::<usize>().ok())
+            {
+                let result = match exit_code {
+                    Some(0) => Ok(pane.clone()),
+                    Some(code) => Err(format!("exit code {} in {}", code, pane)),
+                    None => Err(format!("no exit code in {}", pane)),
+                };
+                self.history.set_exit_code(history_id, *exit_code);
+                self.finish_history(history_id, result);
+                self.refresh_history();
+                should_render = self.pipe_mode || self.mode == Mode::History;
+            }
+
+            let source = match (context.get(CONTEXT_PIPE_ID), context.get(CONTEXT_PLUGIN_ID)) {
+                (Some(pipe_id), _) => Some(PipeSource::Cli(pipe_id.clone())),
+                (None, Some(plugin_id)) => plugin_id.parse::<u32>().ok().map(PipeSource::Plugin),
+                (None, None) => None,
+            };
+            if let Some(source) = source {
+                let mut reply = PipeReply::ok(Action::Run);
+                reply.ok = *exit_code == Some(0);
+                reply.exit_code = *exit_code;
+                reply.pane = Some(pane);
+                self.reply(&source, reply);
+            }
+        }
+
+        if let Event::Timer(_) = event {
+            self.paste_queue.resume();
+            self.process_queue();
+            self.refresh_history();
+            should_render = self.pipe_mode || self.mode == Mode::History;
+        }
+
+        if let Event::RunCommandResult(_, stdout, _, context) = &event {
+            if context.contains_key(CONTEXT_HOST_FACTS) {
+                self.host_facts = Some(guards::parse_facts(&String::from_utf8_lossy(stdout)));
+                if !self.pipe_mode {
+                    self.refresh_playbook();
+                    should_render = true;
+                }
+            }
+        }
+
         if self.pipe_mode {
-            return false;
+            if let Event::Key(key) = event {
+                should_render = self.handle_history_key(key);
+            }
+            return should_render;
         }
 
-        let mut should_render = false;
+        if let Event::RunCommandResult(exit_code, stdout, stderr, context) = &event {
+            if context.contains_key(CONTEXT_SHELL_HISTORY) {
+                self.show_shell_history(stdout);
+                should_render = true;
+            }
+            if context.contains_key(CONTEXT_CHECK_STEP) {
+                self.finish_check(context, *exit_code, stdout, stderr);
+                should_render = true;
+            }
+        }
+
+        // Keep the picker and the current playbook in sync with changes made outside the plugin
+        match &event {
+            Event::FileSystemCreate(paths) | Event::FileSystemDelete(paths) => {
+                if paths.iter().any(|(p, _)| self.is_listed_path(p)) {
+                    let selected = self.files_mgr.get_selected().map(|f| f.name.clone());
+                    if let Err(e) = self
+                        .file_picker
+                        .load_files(&self.get_cwd(), self.sort_files)
+                    {
+                        self.handle_error(format!("Failed to reload files: {}", e));
+                    }
+                    self.files_mgr = self.file_picker.manager();
+                    if self.mode == Mode::FilePicker {
+                        self.files_mgr.with_filter(self.file_filter());
+                    }
+                    if let Some(name) = selected {
+                        self.files_mgr.select_by(|f| f.name == name);
+                    }
+                    should_render = true;
+                }
+
+                if self.is_current_file_changed(paths) {
+                    self.refresh_playbook();
+                    should_render = true;
+                }
+            }
+            Event::FileSystemUpdate(paths) if self.is_current_file_changed(paths) => {
+                self.refresh_playbook();
+                should_render = true;
+            }
+            _ => {}
+        }
 
         if let Event::Key(key) = event {
+            if self.prompt.is_some() {
+                self.handle_prompt_key(key);
+                return true;
+            }
+
             match key.bare_key {
                 // Not configurable keys
                 BareKey::Esc => close_focus(),
@@ -390,6 +2331,14 @@ impl ZellijPlugin for State {
                         self.playbook_mgr.select_down();
                         should_render = true;
                     }
+                    Mode::Panes => {
+                        self.panes_mgr.select_down();
+                        should_render = true;
+                    }
+                    Mode::History => {
+                        self.history_mgr.select_down();
+                        should_render = true;
+                    }
                     _ => {}
                 },
                 BareKey::Up => match self.mode {
@@ -401,18 +2350,22 @@ impl ZellijPlugin for State {
                         self.playbook_mgr.select_up();
                         should_render = true;
                     }
+                    Mode::Panes => {
+                        self.panes_mgr.select_up();
+                        should_render = true;
+                    }
+                    Mode::History => {
+                        self.history_mgr.select_up();
+                        should_render = true;
+                    }
                     _ => {}
                 },
                 BareKey::Right => {
-                    self.mode = self.mode.next();
-                    self.filter_mode = filters::Mode::default();
-                    self.set_filter();
+                    self.switch_mode(self.mode.next());
                     should_render = true;
                 }
                 BareKey::Left => {
-                    self.mode = self.mode.prev();
-                    self.filter_mode = filters::Mode::default();
-                    self.set_filter();
+                    self.switch_mode(self.mode.prev());
                     should_render = true;
                 }
                 BareKey::Char(c)
@@ -421,16 +2374,14 @@ impl ZellijPlugin for State {
                     if let Some(digit) = c.to_digit(10) {
                         if let Ok(mode) = Mode::try_from(digit) {
                             if self.mode != mode {
-                                self.mode = mode;
-                                self.filter_mode = filters::Mode::default();
-                                self.set_filter();
+                                self.switch_mode(mode);
                                 should_render = true;
                             }
                         }
                     }
                 }
                 BareKey::Char(c) if key.has_no_modifiers() => match self.mode {
-                    Mode::FilePicker | Mode::Playbook => {
+                    Mode::FilePicker | Mode::Playbook | Mode::Panes | Mode::History => {
                         if self.filter.is_empty() {
                             if c.is_ascii_digit() {
                                 self.filter_mode = filters::Mode::ID
@@ -458,7 +2409,7 @@ impl ZellijPlugin for State {
                     _ => {}
                 },
                 BareKey::Backspace => match self.mode {
-                    Mode::FilePicker | Mode::Playbook => {
+                    Mode::FilePicker | Mode::Playbook | Mode::Panes | Mode::History => {
                         self.filter.pop();
                         self.set_filter();
                         should_render = true;
@@ -468,6 +2419,10 @@ impl ZellijPlugin for State {
                 BareKey::Enter => match self.mode {
                     Mode::FilePicker => {
                         if let Some(file) = self.files_mgr.get_selected() {
+                            if file.is_shell_history {
+                                self.load_shell_history();
+                                return false;
+                            }
                             let file_name = file.name.clone();
                             if let Err(e) = self.load_file(&file_name) {
                                 self.handle_error(format!(
@@ -483,10 +2438,19 @@ impl ZellijPlugin for State {
                         }
                     }
                     Mode::Playbook => {
-                        if let Some(line) = self.playbook_mgr.get_selected() {
-                            focus_previous_pane();
-                            write_chars(&format!("{}\n", line.content));
-                            focus_previous_pane();
+                        if let Some(line) = self.playbook_mgr.get_selected().cloned() {
+                            self.ask_variables(line, StepAction::Send);
+                            should_render = true;
+                        }
+                    }
+                    Mode::Panes => {
+                        self.broadcast();
+                        should_render = true;
+                    }
+                    Mode::History => {
+                        if let Some(entry) = self.history_mgr.get_selected().cloned() {
+                            self.resend(entry);
+                            should_render = true;
                         }
                     }
                     _ => {}
@@ -496,7 +2460,11 @@ impl ZellijPlugin for State {
                     if self.keybindings.edit.matches(&key) {
                         match self.mode {
                             Mode::FilePicker => {
-                                if let Some(f) = self.files_mgr.get_selected() {
+                                if let Some(f) = self
+                                    .files_mgr
+                                    .get_selected()
+                                    .filter(|f| !f.is_shell_history)
+                                {
                                     let file =
                                         FileToOpen::new(f.name.as_str()).with_cwd(self.get_cwd());
                                     open_file_in_place(file, Default::default());
@@ -530,6 +2498,64 @@ impl ZellijPlugin for State {
                         self.filter_mode = self.filter_mode.switch_to(filters::Mode::ID);
                         self.set_filter();
                         should_render = true;
+                    } else if self.keybindings.edit_step.matches(&key)
+                        && self.mode == Mode::Playbook
+                    {
+                        if let Some(line) = self.playbook_mgr.get_selected().cloned() {
+                            let content = line.content.clone();
+                            self.prompt = Some(Prompt::new(PromptKind::EditStep(line), &content));
+                            should_render = true;
+                        }
+                    } else if self.keybindings.append.matches(&key)
+                        && self.mode == Mode::Playbook
+                        && self.showing_shell_history
+                    {
+                        // Promotes the command into a playbook
+                        if let Some(line) = self.playbook_mgr.get_selected().cloned() {
+                            let file = self.history_file.clone().unwrap_or_default();
+                            self.prompt = Some(Prompt::new(PromptKind::SaveEntry(line), &file));
+                            should_render = true;
+                        }
+                    } else if self.keybindings.append.matches(&key) {
+                        let file = match self.mode {
+                            Mode::FilePicker => self
+                                .files_mgr
+                                .get_selected()
+                                .filter(|f| !f.is_shell_history)
+                                .map(|f| f.name.clone()),
+                            Mode::Playbook => self.current_file.clone(),
+                            _ => None,
+                        };
+                        if let Some(file) = file {
+                            self.prompt = Some(Prompt::new(PromptKind::AppendStep(file), ""));
+                            should_render = true;
+                        }
+                    } else if self.keybindings.details.matches(&key) && self.mode == Mode::Playbook
+                    {
+                        self.show_details = !self.show_details;
+                        should_render = true;
+                    } else if self.keybindings.check.matches(&key) && self.mode == Mode::Playbook {
+                        if let Some(line) = self.playbook_mgr.get_selected().cloned() {
+                            self.ask_variables(line, StepAction::Check);
+                            should_render = true;
+                        }
+                    } else if self.keybindings.expand.matches(&key) && self.mode == Mode::Playbook {
+                        self.toggle_expanded();
+                        should_render = true;
+                    } else if self.keybindings.layout.matches(&key) && self.mode == Mode::Playbook {
+                        self.open_as_layout();
+                        should_render = true;
+                    } else if self.keybindings.mark.matches(&key) && self.mode == Mode::Panes {
+                        self.toggle_mark();
+                        should_render = true;
+                    } else if self.keybindings.record.matches(&key) {
+                        self.toggle_recording();
+                        should_render = true;
+                    } else if self.keybindings.export.matches(&key) && self.mode == Mode::History {
+                        let file =
+                            format!("session-{}.md", history::format_file_stamp(history::now()));
+                        self.prompt = Some(Prompt::new(PromptKind::Export, &file));
+                        should_render = true;
                     }
                 }
             }
@@ -539,32 +2565,103 @@ impl ZellijPlugin for State {
     }
 
     fn pipe(&mut self, pipe_message: PipeMessage) -> bool {
-        if let PipeSource::Cli(_) = pipe_message.source {
-            if let Some(payload) = pipe_message.payload {
-                self.handle_error(payload.clone());
+        if pipe_message.name == PIPE_PRESENCE_NAME {
+            self.handle_presence(&pipe_message);
+            return false;
+        }
+        if !PipeRequest::is_for_us(&pipe_message) {
+            return false;
+        }
 
-                focus_previous_pane();
-                write_chars(&format!("{}\n", payload));
-                focus_previous_pane();
+        let source = pipe_message.source.clone();
+        // Broadcast messages reach every instance, so they are split by role to be handled once
+        let broadcast = !pipe_message.is_private;
+
+        let request = match PipeRequest::parse(&pipe_message) {
+            Ok(request) => request,
+            Err(e) => {
+                // Invalid broadcast requests are answered once, by the pipe mode instance
+                if !broadcast || self.pipe_mode {
+                    self.reply(&source, PipeReply::error(None, e));
+                }
+                return true;
+            }
+        };
+
+        if broadcast && request.action.is_interactive() == self.pipe_mode {
+            // Without a pipe mode instance the caller would wait for nothing
+            if !self.pipe_mode && !self.pipe_mode_running {
+                self.reply(
+                    &source,
+                    PipeReply::error(Some(request.action), "no pipe_mode instance".to_string()),
+                );
+                return true;
             }
+            return false;
         }
-        false
+
+        let action = request.action;
+        match self.handle_pipe_request(request, &source) {
+            Ok(Some(reply)) => self.reply(&source, reply),
+            Ok(None) => {}
+            Err(e) => self.reply(&source, PipeReply::error(Some(action), e)),
+        }
+        self.refresh_history();
+        true
     }
 
     fn render(&mut self, rows: usize, cols: usize) {
-        if self.pipe_mode {
+        if self.render_messages() {
             return;
         }
-        if self.render_errors() {
-            return;
+
+        if self.pipe_mode {
+            self.render_pipe_history(rows, cols);
+        } else {
+            match self.mode {
+                Mode::FilePicker => self.render_file_picker(rows, cols),
+                Mode::Playbook => self.render_playbook(rows, cols),
+                Mode::Usage => self.render_usage(),
+                Mode::Panes => self.render_panes(rows, cols),
+                Mode::History => self.render_history(rows, cols),
+            }
+            if self.recording.is_some() {
+                render::render_recording(&self.theme, cols);
+            }
         }
 
-        match self.mode {
-            Mode::FilePicker => self.render_file_picker(rows, cols),
-            Mode::Playbook => self.render_playbook(rows, cols),
-            Mode::Usage => self.render_usage(),
+        if let Some(prompt) = &self.prompt {
+            render::render_prompt(
+                &self.theme,
+                2,
+                2,
+                cols,
+                prompt.kind.label(),
+                prompt.editor.text(),
+                prompt.editor.cursor(),
+            );
         }
     }
 }
 
+// Unit tests run natively, where the host functions the plugin calls don't exist
+#[cfg(not(test))]
 register_plugin!(State);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Settings that are off unless the configuration enables them
+    #[test]
+    fn opt_in_settings_are_disabled_by_default() {
+        let state = State::default();
+        assert_eq!(state.mode, Mode::FilePicker);
+        assert!(!state.pipe_mode);
+        assert!(!state.pipe_drop_duplicates);
+        assert!(!state.shell_history);
+        assert!(!state.prompt_variables);
+        assert!(state.sort_files);
+        assert!(state.ignore_comments);
+    }
+}
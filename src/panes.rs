@@ -0,0 +1,106 @@
+use crate::tab_manager::TabManager;
+use zellij_tile::prelude::*;
+
+#[derive(Default, Debug, Clone, PartialEq)]
+pub(crate) struct PaneItem {
+    pub id: usize,
+    pub pane_id: u32,
+    pub title: String,
+    pub tab_name: String,
+    pub label: String,
+}
+
+impl PaneItem {
+    fn new(id: usize, pane_id: u32, title: String, tab_name: String) -> Self {
+        let label = format!("[{}] {}", tab_name, title);
+        Self {
+            id,
+            pane_id,
+            title,
+            tab_name,
+            label,
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone)]
+pub(crate) struct Panes {
+    tabs: Vec<TabInfo>,
+    manifest: PaneManifest,
+    items: Vec<PaneItem>,
+}
+
+impl Panes {
+    // Returns true if the list of terminal panes has changed
+    pub(crate) fn update_tabs(&mut self, tabs: Vec<TabInfo>) -> bool {
+        self.tabs = tabs;
+        self.rebuild()
+    }
+
+    // Returns true if the list of terminal panes has changed
+    pub(crate) fn update_manifest(&mut self, manifest: PaneManifest) -> bool {
+        self.manifest = manifest;
+        self.rebuild()
+    }
+
+    fn rebuild(&mut self) -> bool {
+        let mut tabs = self.tabs.clone();
+        tabs.sort_by_key(|t| t.position);
+
+        let mut items = Vec::new();
+        for tab in tabs {
+            let Some(panes) = self.manifest.panes.get(&tab.position) else {
+                continue;
+            };
+            // Only running terminal panes can receive text
+            for pane in panes.iter().filter(|p| !p.is_plugin && !p.exited) {
+                items.push(PaneItem::new(
+                    items.len() + 1,
+                    pane.id,
+                    pane.title.clone(),
+                    tab.name.clone(),
+                ));
+            }
+        }
+
+        if items == self.items {
+            return false;
+        }
+        self.items = items;
+        true
+    }
+
+    pub(crate) fn get(&self, pane_id: u32) -> Option<&PaneItem> {
+        self.items.iter().find(|p| p.pane_id == pane_id)
+    }
+
+    // Prefers an exact title match over a partial one
+    pub(crate) fn find_by_title(&self, title: &str) -> Option<&PaneItem> {
+        self.items
+            .iter()
+            .find(|p| p.title == title)
+            .or_else(|| self.items.iter().find(|p| p.title.contains(title)))
+    }
+
+    // Prefers the focused pane of the tab
+    pub(crate) fn find_in_tab(&self, tab_name: &str) -> Option<u32> {
+        let tab = self.tabs.iter().find(|t| t.name == tab_name)?;
+        let panes: Vec<&PaneInfo> = self
+            .manifest
+            .panes
+            .get(&tab.position)?
+            .iter()
+            .filter(|p| !p.is_plugin && !p.exited)
+            .collect();
+
+        panes
+            .iter()
+            .find(|p| p.is_focused)
+            .or_else(|| panes.first())
+            .map(|p| p.id)
+    }
+
+    pub(crate) fn manager(&self) -> TabManager<PaneItem> {
+        TabManager::new(self.items.clone())
+    }
+}
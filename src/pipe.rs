@@ -0,0 +1,592 @@
+use crate::playbook::PlaybookLine;
+use crate::Mode;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::fmt::Formatter;
+use std::str::FromStr;
+use zellij_tile::prelude::*;
+
+// Messages with other names are meant for other plugins
+const PIPE_NAME_PREFIX: &str = "zellij-playbooks";
+// Name of the messages answering requests from other plugins
+pub(crate) const PIPE_REPLY_NAME: &str = "zellij-playbooks-reply";
+// Name of the messages through which the instances tell each other their role
+pub(crate) const PIPE_PRESENCE_NAME: &str = "zellij-playbooks-presence";
+const ROLE_PIPE_MODE: &str = "pipe_mode";
+const ROLE_INTERACTIVE: &str = "interactive";
+
+const ARG_ACTION: &str = "action";
+const ARG_TARGET: &str = "target";
+const ARG_NEWLINE: &str = "newline";
+pub(crate) const ARG_FILE: &str = "file";
+const ARG_STEP: &str = "step";
+const ARG_MODE: &str = "mode";
+const ARG_LINE: &str = "line";
+pub(crate) const ARG_FORCE: &str = "force";
+const ARGS: [&str; 8] = [
+    ARG_ACTION,
+    ARG_TARGET,
+    ARG_NEWLINE,
+    ARG_FILE,
+    ARG_STEP,
+    ARG_MODE,
+    ARG_LINE,
+    ARG_FORCE,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum Action {
+    Paste,
+    Insert,
+    Run,
+    Load,
+    Select,
+    Append,
+    ListFiles,
+    ListSteps,
+    Mode,
+    Open,
+}
+
+impl Action {
+    // Actions that drive the UI are handled by interactive instances, the rest by the pipe mode instance
+    pub(crate) fn is_interactive(&self) -> bool {
+        matches!(self, Self::Load | Self::Select | Self::Mode | Self::Open)
+    }
+}
+
+impl FromStr for Action {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "paste" => Ok(Self::Paste),
+            "insert" => Ok(Self::Insert),
+            "run" => Ok(Self::Run),
+            "load" => Ok(Self::Load),
+            "select" => Ok(Self::Select),
+            "append" => Ok(Self::Append),
+            "list-files" => Ok(Self::ListFiles),
+            "list-steps" => Ok(Self::ListSteps),
+            "mode" => Ok(Self::Mode),
+            "open" => Ok(Self::Open),
+            _ => Err(format!(
+                "unknown action '{}', expected one of: paste, insert, run, load, select, append, list-files, list-steps, mode, open",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Paste => "paste",
+            Self::Insert => "insert",
+            Self::Run => "run",
+            Self::Load => "load",
+            Self::Select => "select",
+            Self::Append => "append",
+            Self::ListFiles => "list-files",
+            Self::ListSteps => "list-steps",
+            Self::Mode => "mode",
+            Self::Open => "open",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct PipeRequest {
+    pub action: Action,
+    pub payload: Option<String>,
+    pub target: Option<String>,
+    pub newline: bool,
+    pub file: Option<String>,
+    pub step: Option<usize>,
+    pub mode: Option<Mode>,
+    // First and last 1-based lines of the file, for `open`
+    pub lines: Option<(usize, usize)>,
+    // Sends a step that asks for confirmation
+    pub force: bool,
+}
+
+impl PipeRequest {
+    pub(crate) fn is_for_us(pipe_message: &PipeMessage) -> bool {
+        pipe_message.name.starts_with(PIPE_NAME_PREFIX)
+            && pipe_message.name != PIPE_REPLY_NAME
+            && pipe_message.name != PIPE_PRESENCE_NAME
+    }
+
+    // Without arguments the payload is pasted, as before the protocol existed
+    pub(crate) fn parse(pipe_message: &PipeMessage) -> Result<Self, String> {
+        let mut args = pipe_message.args.clone();
+        let mut payload = pipe_message.payload.clone().filter(|p| !p.is_empty());
+
+        // `MessagePlugin` keybindings can't pass arguments, so their payload may hold them as
+        // `key=value,...`. Any other payload is pasted, like the payload of the CLI.
+        if pipe_message.source == PipeSource::Keybind && args.is_empty() {
+            if let Some(p) = payload.take_if(|p| is_args(p)) {
+                args = parse_args(&p)?;
+            }
+        }
+
+        let action = match args.get(ARG_ACTION) {
+            Some(action) => action.trim().parse::<Action>()?,
+            None => Action::Paste,
+        };
+
+        let newline = match args.get(ARG_NEWLINE) {
+            Some(value) => value.trim().parse::<bool>().map_err(|_| {
+                format!("'{ARG_NEWLINE}' argument must be 'true' or 'false', but it's '{value}'")
+            })?,
+            None => action != Action::Insert,
+        };
+
+        let force = match args.get(ARG_FORCE) {
+            Some(value) => value.trim().parse::<bool>().map_err(|_| {
+                format!("'{ARG_FORCE}' argument must be 'true' or 'false', but it's '{value}'")
+            })?,
+            None => false,
+        };
+
+        let step = match args.get(ARG_STEP) {
+            Some(value) => Some(value.trim().parse::<usize>().map_err(|_| {
+                format!("'{ARG_STEP}' argument must be a step id, but it's '{value}'")
+            })?),
+            None => None,
+        };
+
+        let mode = match args.get(ARG_MODE) {
+            Some(value) => Some(value.trim().parse::<Mode>()?),
+            None => None,
+        };
+
+        let lines = match args.get(ARG_LINE) {
+            Some(value) => Some(parse_lines(value)?),
+            None => None,
+        };
+
+        let request = Self {
+            action,
+            payload,
+            target: args.get(ARG_TARGET).cloned(),
+            newline,
+            file: args.get(ARG_FILE).cloned(),
+            step,
+            mode,
+            lines,
+            force,
+        };
+        request.validate()?;
+
+        Ok(request)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        match self.action {
+            Action::Paste | Action::Insert | Action::Run => {
+                if self.payload.is_none() && self.step.is_none() {
+                    return Err(format!(
+                        "'{}' requires a payload or a '{ARG_STEP}' argument",
+                        self.action
+                    ));
+                }
+                // The pipe mode instance never loads a playbook to take the step from
+                if self.payload.is_none() && self.file.is_none() {
+                    return Err(format!(
+                        "'{}' requires a '{ARG_FILE}' argument with '{ARG_STEP}'",
+                        self.action
+                    ));
+                }
+            }
+            Action::Append => {
+                if self.payload.is_none() || self.file.is_none() {
+                    return Err(format!(
+                        "'{}' requires a payload and a '{ARG_FILE}' argument",
+                        self.action
+                    ));
+                }
+            }
+            Action::Load | Action::ListSteps => {
+                if self.file.is_none() {
+                    return Err(format!(
+                        "'{}' requires a '{ARG_FILE}' argument",
+                        self.action
+                    ));
+                }
+            }
+            Action::Select => {
+                if self.step.is_none() {
+                    return Err(format!(
+                        "'{}' requires a '{ARG_STEP}' argument",
+                        self.action
+                    ));
+                }
+            }
+            Action::Mode => {
+                if self.mode.is_none() {
+                    return Err(format!(
+                        "'{}' requires a '{ARG_MODE}' argument",
+                        self.action
+                    ));
+                }
+            }
+            Action::Open => {
+                if self.file.is_none() || self.lines.is_none() {
+                    return Err(format!(
+                        "'{}' requires '{ARG_FILE}' and '{ARG_LINE}' arguments",
+                        self.action
+                    ));
+                }
+            }
+            Action::ListFiles => {}
+        }
+        Ok(())
+    }
+}
+
+// Parses `<line>` or `<first>-<last>`
+fn parse_lines(value: &str) -> Result<(usize, usize), String> {
+    let invalid = || {
+        format!("'{ARG_LINE}' argument must be a line number or a '<first>-<last>' range, but it's '{value}'")
+    };
+    let (first, last) = value.split_once('-').unwrap_or((value, value));
+    let first = first.trim().parse::<usize>().map_err(|_| invalid())?;
+    let last = last.trim().parse::<usize>().map_err(|_| invalid())?;
+    if first == 0 || last < first {
+        return Err(invalid());
+    }
+    Ok((first, last))
+}
+
+// Starts with one of the arguments of the protocol, such as `action=`
+fn is_args(value: &str) -> bool {
+    value
+        .split_once('=')
+        .is_some_and(|(key, _)| ARGS.contains(&key.trim()))
+}
+
+fn parse_args(value: &str) -> Result<BTreeMap<String, String>, String> {
+    value
+        .split(',')
+        .map(|pair| {
+            pair.split_once('=')
+                .map(|(k, v)| (k.trim().to_string(), v.trim().to_string()))
+                .ok_or(format!("'{}' is not a 'key=value' argument", pair))
+        })
+        .collect()
+}
+
+// Result of a pipe request, written as a JSON line to the `zellij pipe` output
+#[derive(Default, Debug, Serialize)]
+pub(crate) struct PipeReply {
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub action: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pane: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+    // The text was identical to one still waiting in the queue and was dropped
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub coalesced: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub step: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub files: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub steps: Option<Vec<PlaybookLine>>,
+}
+
+impl PipeReply {
+    pub(crate) fn ok(action: Action) -> Self {
+        Self {
+            ok: true,
+            action: Some(action.to_string()),
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn error(action: Option<Action>, error_message: String) -> Self {
+        Self {
+            ok: false,
+            action: action.map(|a| a.to_string()),
+            error: Some(error_message),
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_else(|e| {
+            format!(
+                "{{\"ok\":false,\"error\":{}}}",
+                serde_json::Value::String(e.to_string())
+            )
+        })
+    }
+}
+
+// Tells the role of this instance to the given plugin, or to every plugin of the session
+pub(crate) fn announce(pipe_mode: bool, plugin_id: Option<u32>) {
+    let role = if pipe_mode {
+        ROLE_PIPE_MODE
+    } else {
+        ROLE_INTERACTIVE
+    };
+    let mut message = MessageToPlugin::new(PIPE_PRESENCE_NAME).with_payload(role);
+    if let Some(plugin_id) = plugin_id {
+        message = message.with_destination_plugin_id(plugin_id);
+    }
+    pipe_message_to_plugin(message);
+}
+
+// Whether a presence message comes from a pipe mode instance
+pub(crate) fn is_pipe_mode_role(pipe_message: &PipeMessage) -> bool {
+    pipe_message.payload.as_deref() == Some(ROLE_PIPE_MODE)
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct QueuedText {
+    pub action: Action,
+    pub line: PlaybookLine,
+    pub newline: bool,
+    pub source: PipeSource,
+    pub history_id: usize,
+}
+
+impl QueuedText {
+    fn is_duplicate(&self, other: &QueuedText) -> bool {
+        self.line.content == other.line.content
+            && self.line.target == other.line.target
+            && self.newline == other.newline
+    }
+}
+
+// Serializes pasted texts, so that rapid pipe messages neither interleave nor race with focus changes
+#[derive(Default, Debug, Clone)]
+pub(crate) struct PasteQueue {
+    items: VecDeque<QueuedText>,
+    waiting: bool,
+}
+
+impl PasteQueue {
+    // Returns false if the text was dropped as a duplicate of a waiting one
+    pub(crate) fn push(&mut self, item: QueuedText, drop_duplicates: bool) -> bool {
+        if drop_duplicates && self.items.iter().any(|i| i.is_duplicate(&item)) {
+            return false;
+        }
+        self.items.push_back(item);
+        true
+    }
+
+    // Returns None while waiting for the delay between texts
+    pub(crate) fn pop(&mut self) -> Option<QueuedText> {
+        if self.waiting {
+            return None;
+        }
+        self.items.pop_front()
+    }
+
+    pub(crate) fn wait(&mut self) {
+        self.waiting = true;
+    }
+
+    pub(crate) fn resume(&mut self) {
+        self.waiting = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::playbook::Target;
+
+    fn message(source: PipeSource, args: &[(&str, &str)], payload: Option<&str>) -> PipeMessage {
+        PipeMessage {
+            source,
+            name: PIPE_NAME_PREFIX.to_string(),
+            payload: payload.map(str::to_string),
+            args: args
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            is_private: false,
+        }
+    }
+
+    fn cli(args: &[(&str, &str)], payload: Option<&str>) -> Result<PipeRequest, String> {
+        PipeRequest::parse(&message(PipeSource::Cli("1".to_string()), args, payload))
+    }
+
+    fn keybind(payload: &str) -> Result<PipeRequest, String> {
+        PipeRequest::parse(&message(PipeSource::Keybind, &[], Some(payload)))
+    }
+
+    #[test]
+    fn only_requests_are_handled() {
+        let mut msg = message(PipeSource::Keybind, &[], None);
+        assert!(PipeRequest::is_for_us(&msg));
+        for name in [PIPE_REPLY_NAME, PIPE_PRESENCE_NAME, "other-plugin"] {
+            msg.name = name.to_string();
+            assert!(!PipeRequest::is_for_us(&msg), "{}", name);
+        }
+    }
+
+    #[test]
+    fn payload_without_arguments_is_pasted() {
+        let request = cli(&[], Some("ls -la")).unwrap();
+        assert_eq!(request.action, Action::Paste);
+        assert_eq!(request.payload.as_deref(), Some("ls -la"));
+        assert!(request.newline);
+        assert!(!request.force);
+    }
+
+    #[test]
+    fn arguments_are_parsed() {
+        let request = cli(
+            &[
+                ("action", "run"),
+                ("file", "deploy.playbook"),
+                ("step", "5"),
+                ("target", "db"),
+                ("force", "true"),
+            ],
+            None,
+        )
+        .unwrap();
+        assert_eq!(request.action, Action::Run);
+        assert_eq!(request.file.as_deref(), Some("deploy.playbook"));
+        assert_eq!(request.step, Some(5));
+        assert_eq!(request.target.as_deref(), Some("db"));
+        assert!(request.force);
+
+        let request = cli(&[("action", "insert")], Some("git commit")).unwrap();
+        assert!(!request.newline);
+        let request = cli(&[("action", "open"), ("file", "a"), ("line", "3-7")], None).unwrap();
+        assert_eq!(request.lines, Some((3, 7)));
+        let request = cli(&[("action", "mode"), ("mode", "2")], None).unwrap();
+        assert_eq!(request.mode, Some(Mode::Playbook));
+    }
+
+    #[test]
+    fn invalid_values_are_reported() {
+        assert!(cli(&[("action", "launch")], Some("x"))
+            .unwrap_err()
+            .contains("unknown action 'launch'"));
+        assert!(cli(&[("newline", "yes")], Some("x")).is_err());
+        assert!(cli(&[("action", "run"), ("file", "a"), ("step", "two")], None).is_err());
+        assert!(cli(&[("action", "mode"), ("mode", "nowhere")], None).is_err());
+        for lines in ["0", "5-3", "a-b"] {
+            assert!(cli(&[("action", "open"), ("file", "a"), ("line", lines)], None).is_err());
+        }
+    }
+
+    #[test]
+    fn required_arguments_are_validated() {
+        assert!(cli(&[("action", "paste")], None).is_err());
+        assert!(cli(&[("action", "run"), ("step", "5")], None)
+            .unwrap_err()
+            .contains("'file'"));
+        assert!(cli(&[("action", "append")], Some("ls")).is_err());
+        assert!(cli(&[("action", "append"), ("file", "a")], Some("ls")).is_ok());
+        assert!(cli(&[("action", "load")], None).is_err());
+        assert!(cli(&[("action", "select")], None).is_err());
+        assert!(cli(&[("action", "mode")], None).is_err());
+        assert!(cli(&[("action", "open"), ("file", "a")], None).is_err());
+        assert!(cli(&[("action", "list-files")], None).is_ok());
+    }
+
+    #[test]
+    fn keybind_payload_holds_the_arguments() {
+        let request = keybind("action=select, file=deploy.playbook, step=3").unwrap();
+        assert_eq!(request.action, Action::Select);
+        assert_eq!(request.file.as_deref(), Some("deploy.playbook"));
+        assert_eq!(request.step, Some(3));
+        assert_eq!(request.payload, None);
+        assert!(keybind("action=select,step").is_err());
+    }
+
+    #[test]
+    fn other_keybind_payloads_are_pasted() {
+        for payload in ["echo a=b", "FOO=1 make", "kubectl get pods"] {
+            let request = keybind(payload).unwrap();
+            assert_eq!(request.action, Action::Paste, "{}", payload);
+            assert_eq!(request.payload.as_deref(), Some(payload));
+        }
+    }
+
+    #[test]
+    fn cli_payloads_are_never_arguments() {
+        let request = cli(&[], Some("action=mode,mode=1")).unwrap();
+        assert_eq!(request.action, Action::Paste);
+    }
+
+    #[test]
+    fn replies_are_json_lines() {
+        let mut reply = PipeReply::ok(Action::Run);
+        reply.exit_code = Some(1);
+        reply.ok = false;
+        assert_eq!(
+            reply.to_json(),
+            r#"{"ok":false,"action":"run","exit_code":1}"#
+        );
+        assert_eq!(
+            PipeReply::error(None, "no pipe_mode instance".to_string()).to_json(),
+            r#"{"ok":false,"error":"no pipe_mode instance"}"#
+        );
+    }
+
+    fn queued(content: &str, target: Option<Target>, newline: bool) -> QueuedText {
+        QueuedText {
+            action: Action::Paste,
+            line: PlaybookLine {
+                content: content.to_string(),
+                target,
+                ..Default::default()
+            },
+            newline,
+            source: PipeSource::Keybind,
+            history_id: 0,
+        }
+    }
+
+    #[test]
+    fn queue_keeps_the_order_and_waits() {
+        let mut queue = PasteQueue::default();
+        assert!(queue.push(queued("first", None, true), false));
+        assert!(queue.push(queued("second", None, true), false));
+        queue.wait();
+        assert!(queue.pop().is_none());
+        queue.resume();
+        assert_eq!(queue.pop().unwrap().line.content, "first");
+        assert_eq!(queue.pop().unwrap().line.content, "second");
+        assert!(queue.pop().is_none());
+    }
+
+    #[test]
+    fn queue_drops_duplicates_only_when_asked() {
+        let mut queue = PasteQueue::default();
+        queue.wait();
+        assert!(queue.push(queued("ls", None, true), true));
+        assert!(!queue.push(queued("ls", None, true), true));
+        assert!(queue.push(queued("ls", None, false), true));
+        assert!(queue.push(
+            queued("ls", Some(Target::Pane("db".to_string())), true),
+            true
+        ));
+        assert!(queue.push(queued("ls", None, true), false));
+        queue.resume();
+        let mut count = 0;
+        while queue.pop().is_some() {
+            count += 1;
+        }
+        assert_eq!(count, 4);
+    }
+}
@@ -0,0 +1,420 @@
+use crate::guards::Guard;
+use crate::highlight::{self, Token};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fmt::Formatter;
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+
+const SECTION_PREFIX: &str = "## ";
+const DIRECTIVE_PREFIX: &str = "@";
+const DIRECTIVE_PANE: &str = "pane";
+const DIRECTIVE_TAB: &str = "tab";
+const DIRECTIVE_EXPECT: &str = "expect";
+const DIRECTIVE_EXPECT_EXIT: &str = "expect-exit";
+const DIRECTIVE_IF: &str = "if ";
+const DIRECTIVE_ONLY: &str = "only ";
+const SUB_STEP_INDENT: &str = "  > ";
+const DESCRIPTION_SEPARATOR: &str = "  # ";
+
+// Where a step should be pasted, declared with `# @pane: <title>` or `# @tab: <name>`
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub(crate) enum Target {
+    Pane(String),
+    Tab(String),
+}
+
+impl Target {
+    // `pane: <title>` or `tab: <name>`, any other text is a pane title
+    pub(crate) fn parse(text: &str) -> Self {
+        match text.split_once(':') {
+            Some((DIRECTIVE_TAB, name)) => Self::Tab(name.trim().to_string()),
+            Some((DIRECTIVE_PANE, name)) => Self::Pane(name.trim().to_string()),
+            _ => Self::Pane(text.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Pane(name) => write!(f, "{}: {}", DIRECTIVE_PANE, name),
+            Self::Tab(name) => write!(f, "{}: {}", DIRECTIVE_TAB, name),
+        }
+    }
+}
+
+#[derive(Default, Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct PlaybookLine {
+    pub id: usize,
+    pub content: String,
+    // 1-based line in the source file
+    pub line_number: usize,
+    pub target: Option<Target>,
+    pub section: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    // Id of the step this one is a sub-step of, such as a command of a task
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent: Option<usize>,
+    // Metadata of steps of structured playbooks
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    // Values of the `{{name}}` variables, suggested when the step is sent
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub vars: BTreeMap<String, String>,
+    // Ask before the step is sent
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub confirm: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    // Conditions for the step to be sent, and why one of them is false
+    #[serde(skip)]
+    pub guards: Vec<Guard>,
+    #[serde(skip)]
+    pub skipped: Option<String>,
+    // Pattern the output must match and exit code expected when the step is checked
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expect: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expect_exit: Option<i32>,
+}
+
+impl PlaybookLine {
+    // The name, or else the content, followed by the description, if any. Sub-steps are indented.
+    pub(crate) fn label(&self) -> String {
+        let title = self.name.as_ref().unwrap_or(&self.content);
+        match &self.description {
+            Some(description) => format!(
+                "{}{}{}{}",
+                self.indent(),
+                title,
+                DESCRIPTION_SEPARATOR,
+                description
+            ),
+            None => format!("{}{}", self.indent(), title),
+        }
+    }
+
+    // Colored parts of the label, the command and its description as a comment. Names are left plain.
+    pub(crate) fn label_tokens(&self) -> Vec<(Token, Range<usize>)> {
+        if self.name.is_some() {
+            return Vec::new();
+        }
+        let offset = self.indent().chars().count();
+        let mut tokens: Vec<(Token, Range<usize>)> = highlight::tokenize(&self.content)
+            .into_iter()
+            .map(|(token, r)| (token, r.start + offset..r.end + offset))
+            .collect();
+        if let Some(description) = &self.description {
+            // The description ends the label
+            let comment = format!("{}{}", DESCRIPTION_SEPARATOR.trim_start(), description);
+            let end = self.label().chars().count();
+            tokens.push((Token::Comment, end - comment.chars().count()..end));
+        }
+        tokens
+    }
+
+    fn indent(&self) -> &str {
+        if self.parent.is_some() {
+            SUB_STEP_INDENT
+        } else {
+            ""
+        }
+    }
+
+    pub(crate) fn new(id: usize, content: String) -> Self {
+        Self {
+            id,
+            content,
+            ..Default::default()
+        }
+    }
+
+    // The content run in a subshell with the working directory and environment of the step,
+    // so that pasting it leaves the state of the shell alone
+    pub(crate) fn wrapped_content(&self) -> String {
+        if self.cwd.is_none() && self.env.is_empty() {
+            return self.content.clone();
+        }
+
+        let mut commands = Vec::new();
+        if let Some(cwd) = &self.cwd {
+            commands.push(format!("cd {}", shell_quote_dir(cwd)));
+        }
+        for (name, value) in &self.env {
+            commands.push(format!("export {}={}", name, shell_quote(value)));
+        }
+        commands.push(self.content.clone());
+        format!("({})", commands.join(" && "))
+    }
+}
+
+// Annotations collected from directive comments, applied to the next step
+#[derive(Default)]
+struct Directives {
+    target: Option<Target>,
+    guards: Vec<Guard>,
+    expect: Option<String>,
+    expect_exit: Option<i32>,
+}
+
+impl Directives {
+    // Returns false if the line is not a known directive
+    fn parse(&mut self, line: &str) -> bool {
+        let Some(directive) = line
+            .trim()
+            .strip_prefix('#')
+            .map(str::trim_start)
+            .and_then(|l| l.strip_prefix(DIRECTIVE_PREFIX))
+        else {
+            return false;
+        };
+
+        // `@if env:NAME` and `@only host=PATTERN` guards
+        let guard = if let Some(guard) = directive.strip_prefix(DIRECTIVE_IF) {
+            Guard::parse_if(guard)
+        } else if let Some(guard) = directive.strip_prefix(DIRECTIVE_ONLY) {
+            Guard::parse_only(guard)
+        } else {
+            None
+        };
+        if let Some(guard) = guard {
+            self.guards.push(guard);
+            return true;
+        }
+
+        let Some((key, value)) = directive.split_once(':') else {
+            return false;
+        };
+        let value = value.trim().to_string();
+        if value.is_empty() {
+            return false;
+        }
+
+        match key.trim() {
+            DIRECTIVE_PANE => self.target = Some(Target::Pane(value)),
+            DIRECTIVE_TAB => self.target = Some(Target::Tab(value)),
+            // `@expect: "Running"`, the quotes are optional
+            DIRECTIVE_EXPECT => {
+                let pattern = value
+                    .strip_prefix('"')
+                    .and_then(|v| v.strip_suffix('"'))
+                    .unwrap_or(&value);
+                self.expect = Some(pattern.to_string());
+            }
+            DIRECTIVE_EXPECT_EXIT => match value.parse() {
+                Ok(exit_code) => self.expect_exit = Some(exit_code),
+                Err(_) => return false,
+            },
+            _ => return false,
+        }
+        true
+    }
+
+    fn apply(self, line: &mut PlaybookLine) {
+        line.target = self.target;
+        line.guards = self.guards;
+        line.expect = self.expect;
+        line.expect_exit = self.expect_exit;
+    }
+}
+
+// Returns the section name if the line is a `## <name>` header
+fn parse_section(line: &str) -> Option<String> {
+    let name = line.trim().strip_prefix(SECTION_PREFIX)?.trim();
+    if name.is_empty() {
+        return None;
+    }
+    Some(name.to_string())
+}
+
+// Groups steps by section in order of appearance, steps before the first section go to `default`
+pub(crate) fn sections<'a>(
+    lines: impl Iterator<Item = &'a PlaybookLine>,
+    default: &str,
+) -> Vec<(String, Vec<&'a PlaybookLine>)> {
+    let mut sections: Vec<(String, Vec<&PlaybookLine>)> = Vec::new();
+    for line in lines {
+        let name = line.section.as_deref().unwrap_or(default);
+        match sections.iter_mut().find(|(n, _)| n == name) {
+            Some((_, steps)) => steps.push(line),
+            None => sections.push((name.to_string(), vec![line])),
+        }
+    }
+    sections
+}
+
+// The first step within the lines, or the closest step before them if they hold none
+pub(crate) fn step_at<'a>(
+    lines: impl Iterator<Item = &'a PlaybookLine>,
+    first: usize,
+    last: usize,
+) -> Option<&'a PlaybookLine> {
+    let mut closest = None;
+    for line in lines {
+        if line.line_number < first {
+            closest = Some(line);
+        } else if line.line_number <= last {
+            return Some(line);
+        } else {
+            return closest.or(Some(line));
+        }
+    }
+    closest
+}
+
+// `## ` lines are section headers with `sections`, otherwise they are comments like any other
+pub(crate) fn parse(content: &str, ignore_comments: bool, sections: bool) -> Vec<PlaybookLine> {
+    let mut playbook_lines = Vec::new();
+    let mut directives = Directives::default();
+    let mut section = None;
+    let mut id = 1;
+
+    for (index, line) in content.lines().enumerate() {
+        // Skip empty lines
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        // Section headers and directives are never shown as steps
+        if let Some(name) = parse_section(line).filter(|_| sections) {
+            section = Some(name);
+            continue;
+        }
+        if directives.parse(line) {
+            continue;
+        }
+
+        // Skip comments if ignore_comments is enabled
+        if ignore_comments && line.trim().starts_with('#') {
+            continue;
+        }
+
+        let mut playbook_line = PlaybookLine::new(id, line.to_string());
+        playbook_line.line_number = index + 1;
+        playbook_line.section = section.clone();
+        std::mem::take(&mut directives).apply(&mut playbook_line);
+
+        playbook_lines.push(playbook_line);
+        id += 1;
+    }
+
+    playbook_lines
+}
+
+// Single-quotes the text for a POSIX shell, unless it is made of safe characters only
+pub(crate) fn shell_quote(text: &str) -> String {
+    let safe = !text.is_empty()
+        && text
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "/._-~+,:=@%".contains(c));
+    if safe {
+        return text.to_string();
+    }
+    format!("'{}'", text.replace('\'', r"'\''"))
+}
+
+// Quotes a directory for `cd`, leaving a leading `~` or `~user` outside of the quotes
+// so that the shell still expands it
+pub(crate) fn shell_quote_dir(dir: &str) -> String {
+    if !dir.starts_with('~') {
+        return shell_quote(dir);
+    }
+    let (home, rest) = dir.split_once('/').unwrap_or((dir, ""));
+    let valid_home = home[1..]
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || "._-".contains(c));
+    if !valid_home {
+        return shell_quote(dir);
+    }
+    if rest.is_empty() {
+        return dir.to_string();
+    }
+    format!("{}/{}", home, shell_quote(rest))
+}
+
+// Writes the steps as playbook content, preceded by their target directives
+pub(crate) fn render(lines: &[PlaybookLine]) -> String {
+    let mut content = String::new();
+    for line in lines {
+        if let Some(target) = &line.target {
+            content.push_str(&format!("# {}{}\n", DIRECTIVE_PREFIX, target));
+        }
+        content.push_str(&line.content);
+        content.push('\n');
+    }
+    content
+}
+
+// Writes into a temporary file next to the target and renames it, so a failed write never truncates a playbook
+pub(crate) fn write_atomic(path: &Path, content: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let file_name = path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .ok_or("Invalid file name")?;
+    let tmp_path = path.with_file_name(format!(".{}.tmp", file_name));
+
+    fs::write(&tmp_path, content)?;
+    if let Err(e) = fs::rename(&tmp_path, path) {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(e.into());
+    }
+
+    Ok(())
+}
+
+// Replaces the 1-based line if it still holds the original content
+pub(crate) fn replace_line(
+    path: &Path,
+    line_number: usize,
+    original: &str,
+    content: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // Steps built from a payload or the shell history don't come from a line of the file
+    if line_number == 0 {
+        return Err("This step has no source line".into());
+    }
+    let source = fs::read_to_string(path)?;
+    let mut lines: Vec<&str> = source.split_inclusive('\n').collect();
+
+    let line = lines
+        .get(line_number - 1)
+        .ok_or(format!("Line {} does not exist", line_number))?;
+    let ending = &line[line.trim_end_matches(['\r', '\n']).len()..];
+    if line.trim_end_matches(['\r', '\n']) != original {
+        return Err(format!(
+            "Line {} was changed on disk, reload the playbook",
+            line_number
+        )
+        .into());
+    }
+
+    let replacement = format!("{}{}", content, ending);
+    lines[line_number - 1] = &replacement;
+
+    write_atomic(path, &lines.concat())
+}
+
+// Appends the content as new lines, creating the file if needed
+pub(crate) fn append_lines(path: &Path, content: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let mut source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e.into()),
+    };
+
+    if !source.is_empty() && !source.ends_with('\n') {
+        source.push('\n');
+    }
+    source.push_str(content.trim_end_matches(['\r', '\n']));
+    source.push('\n');
+
+    write_atomic(path, &source)
+}
@@ -0,0 +1,295 @@
+use crate::playbook::PlaybookLine;
+use crate::variables::Variable;
+use std::collections::BTreeMap;
+use zellij_tile::prelude::*;
+
+#[derive(Debug, Clone)]
+pub(crate) enum PromptKind {
+    EditStep(PlaybookLine),
+    AppendStep(String),
+    // The editor holds the playbook to save the history entry to
+    SaveEntry(PlaybookLine),
+    // The editor holds the file to export the history to
+    Export,
+    // The editor holds the file to save the steps recorded after the log position to
+    SaveMacro(usize),
+    // The editor holds the value of the first pending variable of the step
+    Variable {
+        line: PlaybookLine,
+        action: StepAction,
+        pending: Vec<Variable>,
+        values: BTreeMap<String, String>,
+    },
+    // Steps marked with `confirm` are sent once Enter is pressed, the editor is unused
+    Confirm {
+        line: PlaybookLine,
+        action: StepAction,
+    },
+}
+
+// What to do with a step once its variables have values
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum StepAction {
+    Send,
+    Broadcast,
+    // Run in the background and compare the outcome with the expectations of the step
+    Check,
+}
+
+impl PromptKind {
+    pub(crate) fn label(&self) -> String {
+        match self {
+            Self::EditStep(line) => format!("Edit step {}", line.id),
+            Self::AppendStep(file) => format!("Append to {}", file),
+            Self::SaveEntry(line) => format!("Save '{}' to playbook", line.content),
+            Self::Export => "Export history to".to_string(),
+            Self::SaveMacro(_) => "Save recorded steps to".to_string(),
+            Self::Variable { line, pending, .. } => format!(
+                "Step {} {}",
+                line.id,
+                pending.first().map(|v| v.name.as_str()).unwrap_or_default()
+            ),
+            Self::Confirm { line, action } => format!(
+                "{} step {} '{}'? Enter to confirm, Esc to cancel",
+                if *action == StepAction::Check {
+                    "Check"
+                } else {
+                    "Send"
+                },
+                line.id,
+                line.name.as_ref().unwrap_or(&line.content)
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Prompt {
+    pub kind: PromptKind,
+    pub editor: LineEditor,
+}
+
+impl Prompt {
+    pub(crate) fn new(kind: PromptKind, text: &str) -> Self {
+        Self {
+            kind,
+            editor: LineEditor::new(text),
+        }
+    }
+}
+
+// Single-line text editor with readline-like keys
+#[derive(Default, Debug, Clone)]
+pub(crate) struct LineEditor {
+    chars: Vec<char>,
+    cursor: usize,
+}
+
+impl LineEditor {
+    pub(crate) fn new(text: &str) -> Self {
+        let chars: Vec<char> = text.chars().collect();
+        let cursor = chars.len();
+        Self { chars, cursor }
+    }
+
+    pub(crate) fn text(&self) -> String {
+        self.chars.iter().collect()
+    }
+
+    pub(crate) fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    // Returns false if the key is not an editing key
+    pub(crate) fn handle_key(&mut self, key: &KeyWithModifier) -> bool {
+        let ctrl = key.has_modifiers(&[KeyModifier::Ctrl]);
+        let alt = key.has_modifiers(&[KeyModifier::Alt]);
+
+        match key.bare_key {
+            BareKey::Left if ctrl || alt => self.word_left(),
+            BareKey::Right if ctrl || alt => self.word_right(),
+            BareKey::Backspace if ctrl || alt => self.delete_word_backward(),
+            BareKey::Left => self.cursor = self.cursor.saturating_sub(1),
+            BareKey::Right => self.cursor = (self.cursor + 1).min(self.chars.len()),
+            BareKey::Home => self.cursor = 0,
+            BareKey::End => self.cursor = self.chars.len(),
+            BareKey::Backspace => {
+                if self.cursor > 0 {
+                    self.cursor -= 1;
+                    self.chars.remove(self.cursor);
+                }
+            }
+            BareKey::Delete => {
+                if self.cursor < self.chars.len() {
+                    self.chars.remove(self.cursor);
+                }
+            }
+            BareKey::Char('a') if ctrl => self.cursor = 0,
+            BareKey::Char('e') if ctrl => self.cursor = self.chars.len(),
+            BareKey::Char('w') if ctrl => self.delete_word_backward(),
+            BareKey::Char('u') if ctrl => {
+                self.chars.drain(..self.cursor);
+                self.cursor = 0;
+            }
+            BareKey::Char('k') if ctrl => self.chars.truncate(self.cursor),
+            BareKey::Char('b') if alt => self.word_left(),
+            BareKey::Char('f') if alt => self.word_right(),
+            BareKey::Char('d') if alt => self.delete_word_forward(),
+            BareKey::Char(c) if !ctrl && !alt => {
+                self.chars.insert(self.cursor, c);
+                self.cursor += 1;
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    fn word_left(&mut self) {
+        while self.cursor > 0 && self.chars[self.cursor - 1].is_whitespace() {
+            self.cursor -= 1;
+        }
+        while self.cursor > 0 && !self.chars[self.cursor - 1].is_whitespace() {
+            self.cursor -= 1;
+        }
+    }
+
+    fn word_right(&mut self) {
+        while self.cursor < self.chars.len() && self.chars[self.cursor].is_whitespace() {
+            self.cursor += 1;
+        }
+        while self.cursor < self.chars.len() && !self.chars[self.cursor].is_whitespace() {
+            self.cursor += 1;
+        }
+    }
+
+    fn delete_word_backward(&mut self) {
+        let end = self.cursor;
+        self.word_left();
+        self.chars.drain(self.cursor..end);
+    }
+
+    fn delete_word_forward(&mut self) {
+        let begin = self.cursor;
+        self.word_right();
+        self.chars.drain(begin..self.cursor);
+        self.cursor = begin;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn press(editor: &mut LineEditor, key: KeyWithModifier) {
+        assert!(editor.handle_key(&key), "{:?} is an editing key", key);
+    }
+
+    fn key(bare_key: BareKey) -> KeyWithModifier {
+        KeyWithModifier::new(bare_key)
+    }
+
+    fn ctrl(bare_key: BareKey) -> KeyWithModifier {
+        KeyWithModifier::new(bare_key).with_ctrl_modifier()
+    }
+
+    fn alt(bare_key: BareKey) -> KeyWithModifier {
+        KeyWithModifier::new(bare_key).with_alt_modifier()
+    }
+
+    #[test]
+    fn cursor_starts_at_the_end() {
+        let editor = LineEditor::new("héllo");
+        assert_eq!(editor.cursor(), 5);
+        assert_eq!(editor.text(), "héllo");
+    }
+
+    #[test]
+    fn typing_inserts_at_the_cursor() {
+        let mut editor = LineEditor::new("ac");
+        press(&mut editor, key(BareKey::Left));
+        press(&mut editor, key(BareKey::Char('b')));
+        assert_eq!(editor.text(), "abc");
+        assert_eq!(editor.cursor(), 2);
+    }
+
+    #[test]
+    fn cursor_stays_within_the_text() {
+        let mut editor = LineEditor::new("ab");
+        press(&mut editor, key(BareKey::Right));
+        assert_eq!(editor.cursor(), 2);
+        for _ in 0..3 {
+            press(&mut editor, key(BareKey::Left));
+        }
+        assert_eq!(editor.cursor(), 0);
+        press(&mut editor, key(BareKey::Backspace));
+        assert_eq!(editor.text(), "ab");
+    }
+
+    #[test]
+    fn home_and_end() {
+        let mut editor = LineEditor::new("echo hi");
+        press(&mut editor, key(BareKey::Home));
+        assert_eq!(editor.cursor(), 0);
+        press(&mut editor, key(BareKey::End));
+        assert_eq!(editor.cursor(), 7);
+        press(&mut editor, ctrl(BareKey::Char('a')));
+        assert_eq!(editor.cursor(), 0);
+        press(&mut editor, ctrl(BareKey::Char('e')));
+        assert_eq!(editor.cursor(), 7);
+    }
+
+    #[test]
+    fn word_movement_skips_blanks() {
+        let mut editor = LineEditor::new("git  commit -m");
+        press(&mut editor, ctrl(BareKey::Left));
+        assert_eq!(editor.cursor(), 12);
+        press(&mut editor, alt(BareKey::Char('b')));
+        assert_eq!(editor.cursor(), 5);
+        press(&mut editor, alt(BareKey::Left));
+        assert_eq!(editor.cursor(), 0);
+        press(&mut editor, ctrl(BareKey::Right));
+        assert_eq!(editor.cursor(), 3);
+        press(&mut editor, alt(BareKey::Char('f')));
+        assert_eq!(editor.cursor(), 11);
+    }
+
+    #[test]
+    fn word_deletion() {
+        let mut editor = LineEditor::new("git commit -m");
+        press(&mut editor, ctrl(BareKey::Char('w')));
+        assert_eq!(editor.text(), "git commit ");
+        press(&mut editor, alt(BareKey::Backspace));
+        assert_eq!(editor.text(), "git ");
+        assert_eq!(editor.cursor(), 4);
+
+        let mut editor = LineEditor::new("git commit -m");
+        press(&mut editor, key(BareKey::Home));
+        press(&mut editor, alt(BareKey::Char('d')));
+        assert_eq!(editor.text(), " commit -m");
+        assert_eq!(editor.cursor(), 0);
+    }
+
+    #[test]
+    fn line_deletion_around_the_cursor() {
+        let mut editor = LineEditor::new("echo hello");
+        for _ in 0..5 {
+            press(&mut editor, key(BareKey::Left));
+        }
+        press(&mut editor, key(BareKey::Delete));
+        assert_eq!(editor.text(), "echo ello");
+        press(&mut editor, ctrl(BareKey::Char('k')));
+        assert_eq!(editor.text(), "echo ");
+        press(&mut editor, key(BareKey::Left));
+        press(&mut editor, ctrl(BareKey::Char('u')));
+        assert_eq!(editor.text(), " ");
+        assert_eq!(editor.cursor(), 0);
+    }
+
+    #[test]
+    fn other_keys_are_left_to_the_caller() {
+        let mut editor = LineEditor::new("x");
+        assert!(!editor.handle_key(&key(BareKey::Enter)));
+        assert!(!editor.handle_key(&ctrl(BareKey::Char('x'))));
+        assert_eq!(editor.text(), "x");
+    }
+}
@@ -1,8 +1,20 @@
-use crate::{Mode, Navigation, BASE_COLOR, RESERVE_ROW_COUNT};
+use crate::highlight::Token;
+use crate::theme::{Color, Theme};
+use crate::{Mode, Navigation, RESERVE_ROW_COUNT};
+use std::collections::BTreeMap;
+use std::ops::{Range, RangeBounds};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 use zellij_tile::prelude::*;
 
+const ELLIPSIS: &str = "...";
+
+// Colored parts of the rows, by id, as character ranges of their text
+pub(crate) type Highlights = BTreeMap<usize, Vec<(Token, Range<usize>)>>;
+
 #[allow(clippy::too_many_arguments)]
 pub(crate) fn render_main_menu<'a>(
+    theme: &Theme,
     rows: usize,
     cols: usize,
     selected: usize,
@@ -10,21 +22,98 @@ pub(crate) fn render_main_menu<'a>(
     mode: Mode,
     filter: String,
     filter_by: String,
+    dimmed: &[usize],
+    highlights: &Highlights,
+    truncate_middle: bool,
     iterator: impl Iterator<Item = (usize, usize, &'a String)>,
 ) {
     let (x, y, width, height) = main_menu_size(rows, cols);
 
-    render_mode(x, y, mode);
+    render_mode(theme, x, y, mode);
+
+    render_search_block(theme, x + 2, y + 2, filter, filter_by);
+
+    render_list(
+        theme,
+        rows,
+        selected,
+        count,
+        (x, y, width, height),
+        dimmed,
+        highlights,
+        truncate_middle,
+        iterator,
+    );
+}
+
+// Render the pipe history of the pipe mode instance, newest entries first
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn render_pipe_history<'a>(
+    theme: &Theme,
+    rows: usize,
+    cols: usize,
+    selected: usize,
+    count: usize,
+    hint: String,
+    dimmed: &[usize],
+    highlights: &Highlights,
+    truncate_middle: bool,
+    iterator: impl Iterator<Item = (usize, usize, &'a String)>,
+) {
+    let (x, y, width, height) = main_menu_size(rows, cols);
+
+    print_text_with_coordinates(
+        paint(Text::new("Pipe history"), theme.header, ..).opaque(),
+        x,
+        y,
+        None,
+        None,
+    );
+    print_text_with_coordinates(
+        paint(Text::new(hint), theme.hint, ..),
+        x + 2,
+        y + 2,
+        None,
+        None,
+    );
 
-    render_search_block(x + 2, y + 2, filter, filter_by);
+    render_list(
+        theme,
+        rows,
+        selected,
+        count,
+        (x, y, width, height),
+        dimmed,
+        highlights,
+        truncate_middle,
+        iterator,
+    );
+}
 
+// Rows whose id is dimmed are greyed out, such as skipped steps. Other rows get their highlights.
+#[allow(clippy::too_many_arguments)]
+fn render_list<'a>(
+    theme: &Theme,
+    rows: usize,
+    selected: usize,
+    count: usize,
+    (x, y, width, height): (usize, usize, usize, usize),
+    dimmed: &[usize],
+    highlights: &Highlights,
+    truncate_middle: bool,
+    iterator: impl Iterator<Item = (usize, usize, &'a String)>,
+) {
+    // The pane is too small to show any row
+    if height == 0 {
+        return;
+    }
     let (begin, end) = if selected >= height {
         (selected + 1 - height, selected)
     } else {
         (0, height - 1)
     };
 
-    render_right_counter(begin, width, y + 3);
+    render_right_counter(theme, begin, width, y + 3);
 
     {
         let mut number = y + 4;
@@ -36,7 +125,16 @@ pub(crate) fn render_main_menu<'a>(
             if i > end {
                 break;
             }
-            let text = prepare_row_text(value.clone(), id, width, selected == i);
+            let text = prepare_row_text(
+                theme,
+                value.clone(),
+                id,
+                width,
+                selected == i,
+                dimmed.contains(&id),
+                highlights.get(&id).map_or(&[], |h| h.as_slice()),
+                truncate_middle,
+            );
 
             print_text_with_coordinates(text, x, number, None, None);
 
@@ -44,10 +142,10 @@ pub(crate) fn render_main_menu<'a>(
         }
     }
 
-    render_all_counter(x + 2, rows, count);
+    render_all_counter(theme, x + 2, rows, count);
 
     if count > end {
-        render_right_counter_with_max(count - 1 - end, count, width, rows);
+        render_right_counter_with_max(theme, count - 1 - end, count, width, rows);
     }
 }
 
@@ -61,31 +159,75 @@ fn main_menu_size(rows: usize, cols: usize) -> (usize, usize, usize, usize) {
     (x, y, width, height)
 }
 
-fn prepare_row_text(row: String, id: usize, max_length: usize, selected: bool) -> Text {
-    let truncated_row = {
-        let formatted = format!("{}. {}", id, row);
-        if formatted.len() > max_length {
-            let truncated_len = max_length.saturating_sub(3);
-            let mut truncated_str = formatted.chars().take(truncated_len).collect::<String>();
-            truncated_str.push_str("...");
-            truncated_str
-        } else {
-            formatted
-        }
+#[allow(clippy::too_many_arguments)]
+fn prepare_row_text(
+    theme: &Theme,
+    row: String,
+    id: usize,
+    max_length: usize,
+    selected: bool,
+    dimmed: bool,
+    highlights: &[(Token, Range<usize>)],
+    truncate_middle: bool,
+) -> Text {
+    let prefix = format!("{}. ", id);
+    let formatted = format!("{}{}", prefix, row);
+    let truncated_row = if truncate_middle {
+        truncate_middle_of(formatted.clone(), max_length)
+    } else {
+        truncate(formatted.clone(), max_length)
     };
     if selected {
-        Text::new(truncated_row).selected().color_range(0, ..)
-    } else {
-        Text::new(truncated_row)
+        return paint(Text::new(truncated_row).selected(), theme.selection, ..);
+    } else if dimmed {
+        return paint(Text::new(truncated_row), theme.dimmed, ..);
+    }
+
+    // Only the part of the row left before the ellipsis keeps its colors
+    let kept = formatted
+        .chars()
+        .zip(truncated_row.chars())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let offset = prefix.chars().count();
+    let mut text = Text::new(truncated_row);
+    for (token, range) in highlights {
+        let begin = range.start + offset;
+        let end = (range.end + offset).min(kept);
+        if begin < end {
+            text = paint(text, token_color(theme, *token), begin..end);
+        }
     }
+    text
 }
 
-pub(crate) fn render_mode(x: usize, y: usize, mode: Mode) {
+fn token_color(theme: &Theme, token: Token) -> Color {
+    match token {
+        Token::Command => theme.command,
+        Token::Flag => theme.flag,
+        Token::String => theme.string,
+        Token::Variable => theme.variable,
+        Token::Operator => theme.operator,
+        Token::Comment => theme.comment,
+        Token::Placeholder => theme.placeholder,
+        Token::Tag => theme.tag,
+    }
+}
+
+// Colors the range of the text, unless the color is none
+fn paint<R: RangeBounds<usize>>(text: Text, color: Color, range: R) -> Text {
+    match color {
+        Some(color) => text.color_range(color, range),
+        None => text,
+    }
+}
+
+pub(crate) fn render_mode(theme: &Theme, x: usize, y: usize, mode: Mode) {
     let key_indication_text = format!("{}{}", BareKey::Left, BareKey::Right);
     let mut shift = x + key_indication_text.chars().count() + 1;
 
     print_text_with_coordinates(
-        Text::new(key_indication_text).color_range(3, ..).opaque(),
+        paint(Text::new(key_indication_text), theme.header, ..).opaque(),
         x,
         y,
         None,
@@ -103,35 +245,331 @@ pub(crate) fn render_mode(x: usize, y: usize, mode: Mode) {
     });
 }
 
-fn render_search_block(x: usize, y: usize, filter: String, filter_by: String) {
+fn render_search_block(theme: &Theme, x: usize, y: usize, filter: String, filter_by: String) {
     let filter = format!("Search (by {}): {}_", filter_by, filter.clone());
 
-    let text = Text::new(filter).color_range(BASE_COLOR, ..6);
+    let text = paint(Text::new(filter), theme.search, ..6);
     print_text_with_coordinates(text, x, y, None, None);
 }
 
 // Render row with All row-counter
-fn render_all_counter(x: usize, y: usize, all: usize) {
+fn render_all_counter(theme: &Theme, x: usize, y: usize, all: usize) {
     let all_count = format!("All: {}", all);
-    let text = Text::new(all_count).color_range(BASE_COLOR, ..);
+    let text = paint(Text::new(all_count), theme.counter, ..);
     print_text_with_coordinates(text, x, y, None, None);
 }
 
 // Render row with right counter with max
-fn render_right_counter_with_max(count: usize, max_count: usize, width: usize, y: usize) {
+fn render_right_counter_with_max(
+    theme: &Theme,
+    count: usize,
+    max_count: usize,
+    width: usize,
+    y: usize,
+) {
     if count == max_count {
         return;
     }
-    render_right_counter(count, width, y);
+    render_right_counter(theme, count, width, y);
 }
 
 // Render row with right counter
-fn render_right_counter(count: usize, width: usize, y: usize) {
+fn render_right_counter(theme: &Theme, count: usize, width: usize, y: usize) {
     if count == 0 {
         return;
     }
     let row = format!("+ {} more  ", count);
-    let x = width - row.len();
-    let text = Text::new(row).color_range(BASE_COLOR, ..);
+    let x = width.saturating_sub(display_width(&row));
+    let text = paint(Text::new(row), theme.counter, ..);
     print_text_with_coordinates(text, x, y, None, None);
 }
+
+// Render the title and description of the playbook above the search block
+pub(crate) fn render_header(theme: &Theme, x: usize, y: usize, width: usize, header: String) {
+    let header = truncate(header, width.saturating_sub(x));
+    let text = paint(Text::new(header), theme.header, ..);
+    print_text_with_coordinates(text, x, y, None, None);
+}
+
+// Render the command of the selected step below the list, for steps listed by name
+pub(crate) fn render_detail(theme: &Theme, x: usize, y: usize, width: usize, command: &str) {
+    let row = format!("$ {}", command.lines().next().unwrap_or_default());
+    let text = paint(
+        Text::new(truncate(row, width.saturating_sub(x))),
+        theme.hint,
+        ..1,
+    );
+    print_text_with_coordinates(text, x, y, None, None);
+}
+
+// Render a titled block of lines, as many as fit below the title
+pub(crate) fn render_panel(
+    theme: &Theme,
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+    title: String,
+    lines: &[String],
+) {
+    let width = width.saturating_sub(x);
+    print_text_with_coordinates(
+        paint(Text::new(truncate(title, width)), theme.header, ..),
+        x,
+        y,
+        None,
+        None,
+    );
+
+    for (i, line) in lines.iter().take(height.saturating_sub(1)).enumerate() {
+        print_text_with_coordinates(
+            Text::new(truncate(line.clone(), width)),
+            x,
+            y + 1 + i,
+            None,
+            None,
+        );
+    }
+}
+
+// Splits the text into lines of at most `width` columns, keeping its own line breaks
+pub(crate) fn wrap(text: &str, width: usize) -> Vec<String> {
+    let width = width.max(1);
+    let mut lines = Vec::new();
+    for line in text.lines() {
+        let mut current = String::new();
+        let mut current_width = 0;
+        for grapheme in line.graphemes(true) {
+            let grapheme_width = display_width(grapheme);
+            if current_width + grapheme_width > width && !current.is_empty() {
+                lines.push(std::mem::take(&mut current));
+                current_width = 0;
+            }
+            current.push_str(grapheme);
+            current_width += grapheme_width;
+        }
+        lines.push(current);
+    }
+    lines
+}
+
+// Columns taken by the text in a terminal, where CJK characters and emoji take two
+fn display_width(text: &str) -> usize {
+    UnicodeWidthStr::width(text)
+}
+
+// The longest run of graphemes that fits in the width, so that combining marks and emoji are never split
+fn fit<'a>(graphemes: impl Iterator<Item = &'a str>, max_width: usize) -> Vec<&'a str> {
+    let mut width = 0;
+    graphemes
+        .take_while(|g| {
+            width += display_width(g);
+            width <= max_width
+        })
+        .collect()
+}
+
+// Cuts the end of the text to fit in the width, marked with `...` if there is room for it
+fn truncate(text: String, max_width: usize) -> String {
+    if display_width(&text) <= max_width {
+        return text;
+    }
+    if max_width <= ELLIPSIS.len() {
+        return fit(text.graphemes(true), max_width).concat();
+    }
+    let mut truncated = fit(text.graphemes(true), max_width - ELLIPSIS.len()).concat();
+    truncated.push_str(ELLIPSIS);
+    truncated
+}
+
+// Cuts the middle of the text instead, to keep the end of long paths and commands visible
+fn truncate_middle_of(text: String, max_width: usize) -> String {
+    if display_width(&text) <= max_width {
+        return text;
+    }
+    // Too narrow to show both ends
+    if max_width < ELLIPSIS.len() + 2 {
+        return truncate(text, max_width);
+    }
+    let room = max_width - ELLIPSIS.len();
+    let head = fit(text.graphemes(true), room / 2).concat();
+    let mut tail = fit(text.graphemes(true).rev(), room - room / 2);
+    tail.reverse();
+    format!("{}{}{}", head, ELLIPSIS, tail.concat())
+}
+
+// Render a single-line editor, scrolled horizontally to keep the cursor visible
+pub(crate) fn render_prompt(
+    theme: &Theme,
+    x: usize,
+    y: usize,
+    width: usize,
+    label: String,
+    text: String,
+    cursor: usize,
+) {
+    let label = format!("{}: ", label);
+    let label_len = label.chars().count();
+    let available = width.saturating_sub(x + display_width(&label) + 1).max(1);
+
+    // Columns of each character, the cursor takes one more after the text
+    let chars: Vec<char> = text.chars().collect();
+    let widths: Vec<usize> = chars.iter().map(|c| c.width().unwrap_or(0)).collect();
+    let mut used = widths.get(cursor).copied().unwrap_or(1);
+    let mut begin = cursor;
+    while begin > 0 && used + widths[begin - 1] <= available {
+        begin -= 1;
+        used += widths[begin];
+    }
+    let mut end = begin;
+    used = 0;
+    while end < chars.len() && used + widths[end] <= available {
+        used += widths[end];
+        end += 1;
+    }
+
+    let mut visible: String = chars[begin..end].iter().collect();
+    if cursor == chars.len() {
+        visible.push('_');
+    }
+
+    let row = format!("{}{}", label, visible);
+    let padding = width.saturating_sub(x + display_width(&row));
+    let text = Text::new(format!("{}{}", row, " ".repeat(padding)));
+    let text = paint(text, theme.search, ..label_len);
+    let cursor = label_len + cursor - begin;
+    let text = paint(text, theme.cursor, cursor..=cursor);
+    print_text_with_coordinates(text, x, y, None, None);
+}
+
+// Render an error or an information message over the top of the pane
+pub(crate) fn render_message(theme: &Theme, message: &str, error: bool) {
+    let (prefix, color) = if error {
+        ("ERROR: ", theme.error)
+    } else {
+        ("INFO: ", theme.info)
+    };
+    let text = paint(
+        Text::new(format!("{}{}", prefix, message)),
+        color,
+        prefix.chars().count()..,
+    );
+    print_text_with_coordinates(text, 1, 1, None, None);
+}
+
+// Render the recording indicator in the top right corner
+pub(crate) fn render_recording(theme: &Theme, cols: usize) {
+    let indicator = "REC";
+    let x = cols.saturating_sub(indicator.len() + 1);
+    let text = paint(Text::new(indicator), theme.header, ..).selected();
+    print_text_with_coordinates(text, x, 0, None, None);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn truncate_keeps_text_that_fits() {
+        assert_eq!(truncate("abc".to_string(), 3), "abc");
+        assert_eq!(truncate("".to_string(), 0), "");
+    }
+
+    #[test]
+    fn truncate_marks_the_cut() {
+        assert_eq!(truncate("abcdefgh".to_string(), 6), "abc...");
+    }
+
+    #[test]
+    fn truncate_without_room_for_the_ellipsis() {
+        assert_eq!(truncate("abcdef".to_string(), 0), "");
+        assert_eq!(truncate("abcdef".to_string(), 1), "a");
+        assert_eq!(truncate("abcdef".to_string(), 2), "ab");
+        assert_eq!(truncate("abcdef".to_string(), 3), "abc");
+    }
+
+    #[test]
+    fn truncate_counts_wide_characters_twice() {
+        assert_eq!(truncate("日本語テキスト".to_string(), 7), "日本...");
+        // Half of a wide character doesn't fit
+        assert_eq!(truncate("日本語".to_string(), 3), "日");
+        assert_eq!(truncate("日本語".to_string(), 1), "");
+    }
+
+    #[test]
+    fn truncate_keeps_combining_marks() {
+        let text = "e\u{301}e\u{301}e\u{301}e\u{301}e\u{301}".to_string();
+        assert_eq!(truncate(text.clone(), 4), "e\u{301}...");
+        assert_eq!(truncate(text, 2), "e\u{301}e\u{301}");
+    }
+
+    #[test]
+    fn truncate_middle_keeps_both_ends() {
+        assert_eq!(
+            truncate_middle_of("/home/user/projects/app".to_string(), 13),
+            "/home...s/app"
+        );
+        assert_eq!(truncate_middle_of("abc".to_string(), 3), "abc");
+    }
+
+    #[test]
+    fn truncate_middle_falls_back_when_narrow() {
+        assert_eq!(truncate_middle_of("abcdefgh".to_string(), 0), "");
+        assert_eq!(truncate_middle_of("abcdefgh".to_string(), 3), "abc");
+        assert_eq!(truncate_middle_of("abcdefgh".to_string(), 4), "a...");
+        assert_eq!(truncate_middle_of("abcdefgh".to_string(), 5), "a...h");
+    }
+
+    #[test]
+    fn truncate_middle_with_wide_characters() {
+        // Each end gets 3 columns, one of them is left empty
+        let truncated = truncate_middle_of("日本語のテキスト".to_string(), 9);
+        assert_eq!(truncated, "日...ト");
+        assert_eq!(display_width(&truncated), 7);
+    }
+
+    #[test]
+    fn fit_stops_before_the_width_is_exceeded() {
+        assert!(fit("abc".graphemes(true), 0).is_empty());
+        assert_eq!(fit("abc".graphemes(true), 2), vec!["a", "b"]);
+        assert_eq!(fit("a日b".graphemes(true), 2), vec!["a"]);
+        assert_eq!(fit("a日b".graphemes(true), 3), vec!["a", "日"]);
+    }
+
+    #[test]
+    fn fit_never_splits_graphemes() {
+        assert_eq!(fit("e\u{301}x".graphemes(true), 1), vec!["e\u{301}"]);
+        let family = "👨\u{200d}👩\u{200d}👧";
+        assert_eq!(fit(family.graphemes(true), 2), vec![family]);
+        assert!(fit(family.graphemes(true), 1).is_empty());
+    }
+
+    #[test]
+    fn wrap_splits_at_the_width() {
+        assert_eq!(wrap("abcdefg", 3), vec!["abc", "def", "g"]);
+        assert_eq!(wrap("ab\ncd", 3), vec!["ab", "cd"]);
+        assert!(wrap("", 3).is_empty());
+    }
+
+    #[test]
+    fn wrap_with_a_zero_width_takes_one_column() {
+        assert_eq!(wrap("abc", 0), vec!["a", "b", "c"]);
+        assert_eq!(wrap("abc", 1), vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn wrap_keeps_wide_characters_whole() {
+        assert_eq!(wrap("日本語", 3), vec!["日", "本", "語"]);
+        assert_eq!(wrap("日本語", 4), vec!["日本", "語"]);
+        // A character wider than the line still gets a line of its own
+        assert_eq!(wrap("a日", 1), vec!["a", "日"]);
+    }
+
+    #[test]
+    fn wrap_keeps_combining_marks() {
+        assert_eq!(
+            wrap("e\u{301}e\u{301}e\u{301}", 2),
+            vec!["e\u{301}e\u{301}", "e\u{301}"]
+        );
+    }
+}
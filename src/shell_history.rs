@@ -0,0 +1,208 @@
+use crate::playbook::PlaybookLine;
+use std::collections::HashSet;
+
+// Precedes the content of each history file in the output of the read script
+const FILE_MARKER: &str = "#zellij-playbooks-history ";
+
+// Only the newest commands are listed
+const MAX_COMMANDS: usize = 5000;
+
+// Prints the bash, zsh and fish history files of the user, each preceded by a marker line
+pub(crate) fn read_script() -> String {
+    format!(
+        r#"for f in "$HOME/.bash_history" "${{ZDOTDIR:-$HOME}}/.zsh_history" "${{XDG_DATA_HOME:-$HOME/.local/share}}/fish/fish_history"; do
+    if [ -r "$f" ]; then echo "{marker}$f"; cat "$f"; echo; fi
+done"#,
+        marker = FILE_MARKER
+    )
+}
+
+enum Format {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+struct Command {
+    // Seconds since the Unix epoch, if the history records it
+    timestamp: Option<u64>,
+    content: String,
+}
+
+// Parses the output of the read script into deduplicated steps, newest first
+pub(crate) fn parse(output: &str) -> Vec<PlaybookLine> {
+    let mut commands = Vec::new();
+    let mut format = Format::Bash;
+    let mut file = Vec::new();
+
+    for line in output.lines() {
+        if let Some(path) = line.strip_prefix(FILE_MARKER) {
+            commands.extend(parse_file(&file, &format));
+            file.clear();
+            format = if path.ends_with("fish_history") {
+                Format::Fish
+            } else if path.ends_with("zsh_history") {
+                Format::Zsh
+            } else {
+                Format::Bash
+            };
+            continue;
+        }
+        file.push(line);
+    }
+    commands.extend(parse_file(&file, &format));
+
+    // Commands without a timestamp keep their position after the previous one
+    let mut last = 0;
+    for command in commands.iter_mut() {
+        match command.timestamp {
+            Some(timestamp) => last = timestamp,
+            None => command.timestamp = Some(last),
+        }
+    }
+    commands.sort_by_key(|c| c.timestamp);
+
+    let mut seen = HashSet::new();
+    commands
+        .into_iter()
+        .rev()
+        .filter(|c| seen.insert(c.content.clone()))
+        .take(MAX_COMMANDS)
+        .enumerate()
+        .map(|(i, c)| PlaybookLine::new(i + 1, c.content))
+        .collect()
+}
+
+fn parse_file(lines: &[&str], format: &Format) -> Vec<Command> {
+    let mut commands: Vec<Command> = Vec::new();
+    let mut timestamp = None;
+    let mut continued = false;
+
+    for line in lines {
+        match format {
+            // `#<timestamp>` lines are written when HISTTIMEFORMAT is set
+            Format::Bash => {
+                if let Some(t) = line.strip_prefix('#').and_then(|t| t.parse::<u64>().ok()) {
+                    timestamp = Some(t);
+                    continue;
+                }
+                commands.push(Command {
+                    timestamp: timestamp.take(),
+                    content: line.to_string(),
+                });
+            }
+            // Extended format `: <timestamp>:<duration>;<command>`, multi-line commands end lines with `\`
+            Format::Zsh => {
+                if continued {
+                    if let Some(last) = commands.last_mut() {
+                        last.content.push('\n');
+                        last.content.push_str(line);
+                    }
+                } else {
+                    let (timestamp, content) =
+                        match line.strip_prefix(": ").and_then(|l| l.split_once(';')) {
+                            Some((meta, content)) => (
+                                meta.split(':').next().and_then(|t| t.trim().parse().ok()),
+                                content,
+                            ),
+                            None => (None, *line),
+                        };
+                    commands.push(Command {
+                        timestamp,
+                        content: content.to_string(),
+                    });
+                }
+                continued = line.ends_with('\\');
+                if continued {
+                    if let Some(last) = commands.last_mut() {
+                        last.content.pop();
+                    }
+                }
+            }
+            // `- cmd: <command>` entries followed by `  when: <timestamp>`
+            Format::Fish => {
+                if let Some(content) = line.strip_prefix("- cmd: ") {
+                    commands.push(Command {
+                        timestamp: None,
+                        content: content.replace("\\n", "\n").replace("\\\\", "\\"),
+                    });
+                } else if let Some(when) = line.trim_start().strip_prefix("when: ") {
+                    if let Some(last) = commands.last_mut() {
+                        last.timestamp = when.trim().parse().ok();
+                    }
+                }
+            }
+        }
+    }
+
+    commands.retain(|c| !c.content.trim().is_empty());
+    commands
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contents(output: &str) -> Vec<String> {
+        parse(output).into_iter().map(|l| l.content).collect()
+    }
+
+    #[test]
+    fn bash_history_is_listed_newest_first() {
+        let output = format!(
+            "{}/home/me/.bash_history\nls\ncd /tmp\n\nmake\n",
+            FILE_MARKER
+        );
+        assert_eq!(contents(&output), ["make", "cd /tmp", "ls"]);
+    }
+
+    #[test]
+    fn bash_timestamps_are_not_commands() {
+        let output = format!(
+            "{}/home/me/.bash_history\n#1700000000\nls\n#1700000100\nmake\n",
+            FILE_MARKER
+        );
+        assert_eq!(contents(&output), ["make", "ls"]);
+    }
+
+    #[test]
+    fn zsh_extended_history_is_parsed() {
+        let output = format!(
+            "{}/home/me/.zsh_history\n: 1700000000:0;git status\n: 1700000200:3;echo a \\\nb\n: 1700000100:0;ls;pwd\n",
+            FILE_MARKER
+        );
+        assert_eq!(contents(&output), ["echo a \nb", "ls;pwd", "git status"]);
+    }
+
+    #[test]
+    fn zsh_plain_history_is_parsed() {
+        let output = format!("{}/home/me/.zsh_history\nls\nmake\n", FILE_MARKER);
+        assert_eq!(contents(&output), ["make", "ls"]);
+    }
+
+    #[test]
+    fn fish_history_is_parsed() {
+        let output = format!(
+            "{}/home/me/.local/share/fish/fish_history\n- cmd: ls\n  when: 1700000000\n- cmd: echo a\\nb\n  when: 1700000300\n",
+            FILE_MARKER
+        );
+        assert_eq!(contents(&output), ["echo a\nb", "ls"]);
+    }
+
+    #[test]
+    fn histories_are_merged_by_time_and_deduplicated() {
+        let output = format!(
+            "{m}/home/me/.bash_history\n#1700000100\nmake\n#1700000400\nls\n{m}/home/me/.zsh_history\n: 1700000200:0;ls\n: 1700000300:0;git push\n",
+            m = FILE_MARKER
+        );
+        let steps = parse(&output);
+        let contents: Vec<_> = steps.iter().map(|l| l.content.as_str()).collect();
+        assert_eq!(contents, ["ls", "git push", "make"]);
+        assert_eq!(steps.iter().map(|l| l.id).collect::<Vec<_>>(), [1, 2, 3]);
+    }
+
+    #[test]
+    fn missing_histories_list_nothing() {
+        assert!(parse("").is_empty());
+    }
+}
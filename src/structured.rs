@@ -0,0 +1,288 @@
+use crate::guards::Guard;
+use crate::playbook::{PlaybookLine, Target};
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+enum Format {
+    Yaml,
+    Toml,
+}
+
+fn format(file_path: &str) -> Option<Format> {
+    if file_path.ends_with(".playbook.yaml") || file_path.ends_with(".playbook.yml") {
+        Some(Format::Yaml)
+    } else if file_path.ends_with(".playbook.toml") {
+        Some(Format::Toml)
+    } else {
+        None
+    }
+}
+
+pub(crate) fn is_structured(file_path: &str) -> bool {
+    format(file_path).is_some()
+}
+
+// A step of a `.playbook.yaml` or `.playbook.toml` file
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Step {
+    name: Option<String>,
+    cmd: String,
+    description: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    vars: BTreeMap<String, String>,
+    #[serde(default)]
+    confirm: bool,
+    // `pane: <title>`, `tab: <name>`, or a pane title
+    target: Option<String>,
+    cwd: Option<String>,
+    #[serde(default)]
+    env: BTreeMap<String, String>,
+    // Guards, `env:NAME` or `file:PATH`, and `host=PATTERN` or `os=PATTERN`
+    #[serde(rename = "if")]
+    condition: Option<String>,
+    only: Option<String>,
+    // Pattern the output must match and exit code expected when the step is checked
+    expect: Option<String>,
+    expect_exit: Option<i32>,
+}
+
+// Returns None if the file is not a structured playbook.
+// Steps are read one by one, so that an error names the step it was found in.
+pub(crate) fn parse(
+    file_path: &str,
+    content: &str,
+) -> Result<Option<Vec<PlaybookLine>>, Box<dyn std::error::Error>> {
+    let steps: Vec<Result<Step, String>> = match format(file_path) {
+        None => return Ok(None),
+        Some(Format::Yaml) => {
+            let playbook: serde_yaml::Value = serde_yaml::from_str(content)?;
+            let steps = match playbook.get("steps") {
+                Some(serde_yaml::Value::Sequence(steps)) => steps.clone(),
+                Some(_) => return Err("`steps` must be a list".into()),
+                None => Vec::new(),
+            };
+            steps
+                .into_iter()
+                .map(|s| serde_yaml::from_value(s).map_err(|e| e.to_string()))
+                .collect()
+        }
+        Some(Format::Toml) => {
+            let mut playbook: toml::Table = toml::from_str(content)?;
+            let steps = match playbook.remove("steps") {
+                Some(toml::Value::Array(steps)) => steps,
+                Some(_) => return Err("`steps` must be an array of tables, `[[steps]]`".into()),
+                None => Vec::new(),
+            };
+            steps
+                .into_iter()
+                .map(|s| Step::deserialize(s).map_err(|e| e.to_string()))
+                .collect()
+        }
+    };
+
+    let mut lines = Vec::new();
+    let mut from = 0;
+    for (i, step) in steps.into_iter().enumerate() {
+        let step = step.map_err(|e| format!("step {}: {}", i + 1, e.trim()))?;
+        let line_number = line_of(content, &step, from);
+        from = line_number;
+        let line =
+            to_line(i + 1, step, line_number).map_err(|e| format!("step {}: {}", i + 1, e))?;
+        lines.push(line);
+    }
+    Ok(Some(lines))
+}
+
+fn to_line(id: usize, step: Step, line_number: usize) -> Result<PlaybookLine, String> {
+    let mut guards = Vec::new();
+    if let Some(condition) = &step.condition {
+        let guard = Guard::parse_if(condition).ok_or(format!(
+            "invalid `if` guard '{}', expected `env:NAME` or `file:PATH`",
+            condition
+        ))?;
+        guards.push(guard);
+    }
+    if let Some(Err(e)) = step.expect.as_deref().map(Regex::new) {
+        return Err(format!("invalid `expect` pattern: {}", e));
+    }
+    if let Some(only) = &step.only {
+        let guard = Guard::parse_only(only).ok_or(format!(
+            "invalid `only` guard '{}', expected `host=PATTERN` or `os=PATTERN`",
+            only
+        ))?;
+        guards.push(guard);
+    }
+
+    Ok(PlaybookLine {
+        id,
+        content: step.cmd,
+        line_number,
+        target: step.target.as_deref().map(Target::parse),
+        description: step.description,
+        name: step.name,
+        tags: step.tags,
+        vars: step.vars,
+        confirm: step.confirm,
+        cwd: step.cwd,
+        env: step.env,
+        guards,
+        expect: step.expect,
+        expect_exit: step.expect_exit,
+        ..Default::default()
+    })
+}
+
+// 1-based line of the step's `name` key, or else its `cmd` key, searched after the previous step.
+// Keys are matched in the `key: value`, `- key: value`, `{key: value}` and `key = value` forms,
+// so that a command mentioning the name of a step doesn't stand for it.
+fn line_of(content: &str, step: &Step, from: usize) -> usize {
+    let (key, needle) = match step.name.as_deref() {
+        Some(name) => ("name", name),
+        None => ("cmd", step.cmd.lines().next().unwrap_or_default()),
+    };
+    let Ok(key) = Regex::new(&format!(r"(?:^|[\s{{,]){}\s*[:=]", key)) else {
+        return from;
+    };
+
+    let lines: Vec<&str> = content.lines().collect();
+    (from..lines.len())
+        .find(|&i| {
+            let Some(found) = key.find(lines[i]) else {
+                return false;
+            };
+            let value = lines[i][found.end()..].trim();
+            // Block scalars and multi-line strings start on the next line
+            let multiline = value.starts_with(['|', '>']) || value == "\"\"\"" || value == "'''";
+            if multiline {
+                lines.get(i + 1).is_some_and(|l| l.contains(needle))
+            } else {
+                value.contains(needle)
+            }
+        })
+        .map(|i| i + 1)
+        .unwrap_or(from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn steps(file_path: &str, content: &str) -> Vec<PlaybookLine> {
+        parse(file_path, content).unwrap().unwrap()
+    }
+
+    #[test]
+    fn other_files_are_not_structured() {
+        assert!(parse("deploy.playbook", "steps: []").unwrap().is_none());
+        assert!(parse("deploy.yaml", "steps: []").unwrap().is_none());
+        assert!(is_structured("deploy.playbook.yml"));
+    }
+
+    #[test]
+    fn yaml_steps_are_parsed() {
+        let content = "\
+steps:
+  - name: build
+    cmd: cargo build
+    tags: [rust]
+    vars:
+      profile: dev
+    target: 'tab: build'
+    confirm: true
+  - cmd: cargo test
+    if: env:CI
+    only: os=linux
+    expect: ok
+    expect_exit: 0
+";
+        let lines = steps("ci.playbook.yaml", content);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].id, 1);
+        assert_eq!(lines[0].name.as_deref(), Some("build"));
+        assert_eq!(lines[0].content, "cargo build");
+        assert_eq!(lines[0].tags, ["rust"]);
+        assert_eq!(lines[0].vars["profile"], "dev");
+        assert_eq!(lines[0].target, Some(Target::Tab("build".to_string())));
+        assert!(lines[0].confirm);
+        assert_eq!(lines[0].line_number, 2);
+        assert_eq!(lines[1].content, "cargo test");
+        assert_eq!(lines[1].guards.len(), 2);
+        assert_eq!(lines[1].expect.as_deref(), Some("ok"));
+        assert_eq!(lines[1].expect_exit, Some(0));
+        assert_eq!(lines[1].line_number, 9);
+    }
+
+    #[test]
+    fn toml_steps_are_parsed() {
+        let content = "\
+[[steps]]
+name = \"build\"
+cmd = \"cargo build\"
+
+[[steps]]
+cmd = \"\"\"
+cargo test
+\"\"\"
+";
+        let lines = steps("ci.playbook.toml", content);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].line_number, 2);
+        assert_eq!(lines[1].content, "cargo test\n");
+        assert_eq!(lines[1].line_number, 6);
+    }
+
+    #[test]
+    fn errors_name_the_step() {
+        let error = parse(
+            "ci.playbook.yaml",
+            "steps:\n  - cmd: ls\n  - cmd: pwd\n    colour: red\n",
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(
+            error.starts_with("step 2: unknown field `colour`"),
+            "{}",
+            error
+        );
+
+        let error = parse("ci.playbook.yaml", "steps:\n  - cmd: ls\n    if: always\n")
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("invalid `if` guard 'always'"), "{}", error);
+
+        let error = parse("ci.playbook.yaml", "steps:\n  - cmd: ls\n    expect: '('\n")
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("invalid `expect` pattern"), "{}", error);
+
+        assert!(parse("ci.playbook.yaml", "steps: ls").is_err());
+        assert!(parse("ci.playbook.toml", "steps = \"ls\"").is_err());
+    }
+
+    #[test]
+    fn steps_are_found_by_their_key() {
+        let content = "\
+steps:
+  - name: deploy
+    cmd: echo build
+  - name: build
+    cmd: make
+  - {cmd: make check}
+  - cmd: |
+      make install
+";
+        let lines = steps("ci.playbook.yaml", content);
+        let numbers: Vec<_> = lines.iter().map(|l| l.line_number).collect();
+        assert_eq!(numbers, [2, 4, 6, 7]);
+    }
+
+    #[test]
+    fn missing_steps_are_empty() {
+        assert!(steps("ci.playbook.yaml", "title: nothing\n").is_empty());
+        assert!(steps("ci.playbook.toml", "").is_empty());
+    }
+}
@@ -42,6 +42,12 @@ impl<T: Clone> TabManager<T> {
         self.selected = 0;
     }
 
+    pub(crate) fn select_by(&mut self, f: impl Fn(&T) -> bool) {
+        if let Some(position) = self.items.iter().position(f) {
+            self.selected = position;
+        }
+    }
+
     pub(crate) fn get_selected(&self) -> Option<&T> {
         self.items.get(self.selected)
     }
@@ -64,6 +70,10 @@ impl<T: Clone> TabManager<T> {
         self.reset_selection();
     }
 
+    pub(crate) fn origin(&self) -> impl Iterator<Item = &T> {
+        self.origin.iter()
+    }
+
     pub(crate) fn iter(&self) -> impl Iterator<Item = (usize, &T)> {
         self.items.iter().enumerate()
     }
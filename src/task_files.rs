@@ -0,0 +1,504 @@
+use crate::playbook::PlaybookLine;
+use serde_yaml::Value;
+use std::collections::HashSet;
+use std::path::Path;
+
+// Task runner files whose targets are listed as steps instead of their lines
+enum Kind {
+    Make,
+    Just,
+    Task,
+    Npm,
+}
+
+fn kind(file_path: &str) -> Option<Kind> {
+    let name = Path::new(file_path).file_name()?.to_str()?;
+    match name {
+        "Makefile" | "makefile" | "GNUmakefile" => Some(Kind::Make),
+        "justfile" | "Justfile" => Some(Kind::Just),
+        "package.json" => Some(Kind::Npm),
+        _ if name.ends_with(".mk") => Some(Kind::Make),
+        _ if is_taskfile(name) => Some(Kind::Task),
+        _ => None,
+    }
+}
+
+// `Taskfile.yml`, `taskfile.yaml`, `Taskfile.dist.yml`...
+fn is_taskfile(name: &str) -> bool {
+    let name = name.to_lowercase();
+    ["taskfile", "taskfile.dist"]
+        .iter()
+        .any(|base| name == format!("{}.yml", base) || name == format!("{}.yaml", base))
+}
+
+pub(crate) fn is_task_file(file_path: &str) -> bool {
+    kind(file_path).is_some()
+}
+
+// Returns None if the file is not a task runner file
+pub(crate) fn parse(
+    file_path: &str,
+    content: &str,
+) -> Result<Option<Vec<PlaybookLine>>, Box<dyn std::error::Error>> {
+    let Some(kind) = kind(file_path) else {
+        return Ok(None);
+    };
+    let steps = match kind {
+        Kind::Make => parse_makefile(content),
+        Kind::Just => parse_justfile(content),
+        Kind::Task => parse_taskfile(content)?,
+        Kind::Npm => parse_package_json(content)?,
+    };
+    Ok(Some(steps))
+}
+
+fn step(id: usize, content: String, index: usize, description: Option<String>) -> PlaybookLine {
+    let mut line = PlaybookLine::new(id, content);
+    line.line_number = index + 1;
+    line.description = description;
+    line
+}
+
+// Comment lines right above a target, or a trailing `## text` after it, describe it
+fn parse_makefile(content: &str) -> Vec<PlaybookLine> {
+    let mut steps = Vec::new();
+    let mut seen = HashSet::new();
+    let mut comments: Vec<String> = Vec::new();
+
+    for (index, line) in content.lines().enumerate() {
+        if let Some(comment) = line.strip_prefix('#') {
+            comments.push(comment.trim_start_matches('#').trim().to_string());
+            continue;
+        }
+        let doc = std::mem::take(&mut comments).join(" ");
+
+        // Recipes are indented
+        if line.starts_with(['\t', ' ']) || line.trim().is_empty() {
+            continue;
+        }
+        let Some((targets, rest)) = line.split_once(':') else {
+            continue;
+        };
+        // Variable assignments, pattern rules and computed targets are not steps
+        if rest.starts_with('=') || rest.starts_with(":=") || targets.contains(['=', '%', '$']) {
+            continue;
+        }
+
+        let description = rest
+            .split_once("##")
+            .map(|(_, d)| d.trim().to_string())
+            .filter(|d| !d.is_empty())
+            .or(Some(doc).filter(|d| !d.is_empty()));
+
+        for target in targets.split_whitespace() {
+            // Special targets such as `.PHONY`
+            if target.starts_with('.') || !seen.insert(target.to_string()) {
+                continue;
+            }
+            steps.push(step(
+                steps.len() + 1,
+                format!("make {}", target),
+                index,
+                description.clone(),
+            ));
+        }
+    }
+    steps
+}
+
+// Recipe parameters become `{{name}}` placeholders, with their defaults
+fn parse_justfile(content: &str) -> Vec<PlaybookLine> {
+    let mut steps = Vec::new();
+    let mut comments: Vec<String> = Vec::new();
+    let mut private = false;
+
+    for (index, line) in content.lines().enumerate() {
+        if let Some(comment) = line.strip_prefix('#') {
+            // Shebang lines of the file are not documentation
+            if !comment.starts_with('!') {
+                comments.push(comment.trim().to_string());
+            }
+            continue;
+        }
+        if line.starts_with('[') {
+            private |= line.contains("private");
+            continue;
+        }
+        let doc = std::mem::take(&mut comments).join(" ");
+        let is_private = std::mem::take(&mut private);
+
+        if line.starts_with([' ', '\t']) || line.trim().is_empty() {
+            continue;
+        }
+        let first_word = line.split_whitespace().next().unwrap_or_default();
+        if matches!(first_word, "set" | "alias" | "export" | "import" | "mod") {
+            continue;
+        }
+        let Some(colon) = find_unquoted(line, ':') else {
+            continue;
+        };
+        // `name := value` assignments
+        if line[colon + 1..].starts_with('=') {
+            continue;
+        }
+
+        let mut words = split_unquoted(line[..colon].trim_start_matches('@'));
+        if words.is_empty() {
+            continue;
+        }
+        let name = words.remove(0);
+        if is_private || name.starts_with('_') {
+            continue;
+        }
+
+        let mut command = format!("just {}", name);
+        for word in words {
+            let variadic = word.starts_with('*');
+            let word = word.trim_start_matches(['+', '*', '$']);
+            let placeholder = match word.split_once('=') {
+                Some((param, default)) => format!("{{{{{}={}}}}}", param, unquote(default)),
+                None if variadic => format!("{{{{{}=}}}}", word),
+                None => format!("{{{{{}}}}}", word),
+            };
+            command.push(' ');
+            command.push_str(&placeholder);
+        }
+
+        let description = Some(doc).filter(|d| !d.is_empty());
+        steps.push(step(steps.len() + 1, command, index, description));
+    }
+    steps
+}
+
+// Tasks with their `desc`, followed by their `cmds` as sub-steps numbered after the last task
+fn parse_taskfile(content: &str) -> Result<Vec<PlaybookLine>, Box<dyn std::error::Error>> {
+    let taskfile: Value = serde_yaml::from_str(content)?;
+    let Some(tasks) = taskfile.get("tasks").and_then(Value::as_mapping) else {
+        return Ok(Vec::new());
+    };
+
+    let mut steps: Vec<(PlaybookLine, Vec<String>)> = Vec::new();
+    for (name, task) in tasks {
+        let Some(name) = name.as_str() else {
+            continue;
+        };
+        if task.get("internal").and_then(Value::as_bool) == Some(true) {
+            continue;
+        }
+
+        let description = ["desc", "summary"]
+            .iter()
+            .find_map(|key| task.get(key).and_then(Value::as_str))
+            .map(|d| d.trim().to_string())
+            .filter(|d| !d.is_empty());
+        let index = line_of(content, name);
+        let parent = step(
+            steps.len() + 1,
+            format!("task {}", name),
+            index,
+            description,
+        );
+
+        // A task may be a single command, a list of commands or a map with `cmds`
+        let cmds = match task {
+            Value::String(_) | Value::Sequence(_) => Some(task),
+            _ => task.get("cmds").or(task.get("cmd")),
+        };
+        let cmds = match cmds {
+            Some(Value::Sequence(cmds)) => cmds.iter().filter_map(command).collect(),
+            Some(cmd) => command(cmd).into_iter().collect(),
+            None => Vec::new(),
+        };
+        steps.push((parent, cmds));
+    }
+
+    let mut id = steps.len();
+    let mut lines = Vec::new();
+    for (parent, cmds) in steps {
+        let (parent_id, index) = (parent.id, parent.line_number - 1);
+        lines.push(parent);
+        for cmd in cmds {
+            id += 1;
+            let mut sub_step = step(id, cmd, index, None);
+            sub_step.parent = Some(parent_id);
+            lines.push(sub_step);
+        }
+    }
+    Ok(lines)
+}
+
+// `cmd` strings or maps, and calls of other tasks; deferred commands are skipped
+fn command(cmd: &Value) -> Option<String> {
+    if let Some(cmd) = cmd.as_str() {
+        return Some(cmd.trim().to_string());
+    }
+    if let Some(cmd) = cmd.get("cmd").and_then(Value::as_str) {
+        return Some(cmd.trim().to_string());
+    }
+    cmd.get("task")
+        .and_then(Value::as_str)
+        .map(|task| format!("task {}", task))
+}
+
+// The 0-based line declaring the task, used to jump to it from an editor
+fn line_of(content: &str, name: &str) -> usize {
+    content
+        .lines()
+        .position(|l| {
+            let key = l.trim();
+            let key = key.strip_prefix('"').unwrap_or(key);
+            key.strip_prefix(name)
+                .is_some_and(|rest| rest.starts_with(':') || rest.starts_with("\":"))
+        })
+        .unwrap_or_default()
+}
+
+// Scripts in alphabetical order, described by their command
+fn parse_package_json(content: &str) -> Result<Vec<PlaybookLine>, Box<dyn std::error::Error>> {
+    let package: serde_json::Value = serde_json::from_str(content)?;
+    let Some(scripts) = package.get("scripts").and_then(|s| s.as_object()) else {
+        return Ok(Vec::new());
+    };
+
+    Ok(scripts
+        .iter()
+        .enumerate()
+        .map(|(i, (name, script))| {
+            let description = script.as_str().map(str::to_string);
+            let index = line_of(content, name);
+            step(i + 1, format!("npm run {}", name), index, description)
+        })
+        .collect())
+}
+
+fn find_unquoted(line: &str, needle: char) -> Option<usize> {
+    let mut quote = None;
+    for (i, c) in line.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == needle => return Some(i),
+            None => {}
+        }
+    }
+    None
+}
+
+// Splits on whitespace outside of quotes, keeping the quotes
+fn split_unquoted(text: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut quote = None;
+    for c in text.chars() {
+        match quote {
+            Some(q) if c == q => {
+                quote = None;
+                word.push(c);
+            }
+            Some(_) => word.push(c),
+            None if c == '"' || c == '\'' => {
+                quote = Some(c);
+                word.push(c);
+            }
+            None if c.is_whitespace() => {
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+            }
+            None => word.push(c),
+        }
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+fn unquote(value: &str) -> &str {
+    for quote in ['"', '\''] {
+        if let Some(inner) = value
+            .strip_prefix(quote)
+            .and_then(|v| v.strip_suffix(quote))
+        {
+            return inner;
+        }
+    }
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn steps(file_path: &str, content: &str) -> Vec<PlaybookLine> {
+        parse(file_path, content).unwrap().unwrap()
+    }
+
+    fn contents(lines: &[PlaybookLine]) -> Vec<&str> {
+        lines.iter().map(|l| l.content.as_str()).collect()
+    }
+
+    #[test]
+    fn task_files_are_recognized_by_name() {
+        for name in ["Makefile", "dir/GNUmakefile", "rules.mk", "justfile"] {
+            assert!(is_task_file(name), "{}", name);
+        }
+        for name in ["deploy.playbook", "Makefile.bak", "tasks.yml"] {
+            assert!(!is_task_file(name), "{}", name);
+        }
+        assert!(parse("notes.md", "build:").unwrap().is_none());
+    }
+
+    #[test]
+    fn makefile_targets() {
+        let content = "\
+CC := gcc
+.PHONY: build test
+# Builds the app
+build: deps ## Compile
+\t$(CC) main.c
+test lint:
+\tmake check
+%.o: %.c
+build:
+";
+        let lines = steps("Makefile", content);
+        assert_eq!(
+            contents(&lines),
+            vec!["make build", "make test", "make lint"]
+        );
+        assert_eq!(lines[0].description.as_deref(), Some("Compile"));
+        assert_eq!(lines[0].line_number, 4);
+        assert_eq!(lines[1].description, None);
+        assert_eq!(lines[2].line_number, 6);
+    }
+
+    #[test]
+    fn makefile_comments_describe_the_next_target() {
+        let lines = steps("Makefile", "## Runs the tests\ntest:\n\tcargo test\n");
+        assert_eq!(lines[0].description.as_deref(), Some("Runs the tests"));
+    }
+
+    #[test]
+    fn justfile_recipes_with_parameters() {
+        let content = "\
+#!/usr/bin/env just
+set shell := [\"bash\", \"-c\"]
+version := \"1.0\"
+
+# Deploys the app
+deploy env target='web server' +flags:
+    ./deploy.sh {{env}}
+
+[private]
+helper:
+    true
+
+_hidden:
+    true
+
+@quiet *args:
+    echo {{args}}
+";
+        let lines = steps("justfile", content);
+        assert_eq!(
+            contents(&lines),
+            vec![
+                "just deploy {{env}} {{target=web server}} {{flags}}",
+                "just quiet {{args=}}",
+            ]
+        );
+        assert_eq!(lines[0].description.as_deref(), Some("Deploys the app"));
+        assert_eq!(lines[0].line_number, 6);
+    }
+
+    #[test]
+    fn taskfiles_and_package_json_are_recognized_by_name() {
+        for name in [
+            "Taskfile.yml",
+            "taskfile.dist.yaml",
+            "Taskfile.yaml",
+            "package.json",
+        ] {
+            assert!(is_task_file(name), "{}", name);
+        }
+        assert!(!is_task_file("Taskfile.json"));
+    }
+
+    #[test]
+    fn taskfile_tasks_and_their_commands() {
+        let content = "\
+version: '3'
+tasks:
+  build:
+    desc: Build it
+    cmds:
+      - go build
+      - cmd: go vet
+      - task: lint
+      - defer: rm -rf tmp
+  lint: golangci-lint run
+  secret:
+    internal: true
+    cmds: [echo hidden]
+";
+        let lines = steps("Taskfile.yml", content);
+        assert_eq!(
+            contents(&lines),
+            vec![
+                "task build",
+                "go build",
+                "go vet",
+                "task lint",
+                "task lint",
+                "golangci-lint run"
+            ]
+        );
+        assert_eq!(lines[0].description.as_deref(), Some("Build it"));
+        assert_eq!(lines[0].line_number, 3);
+        assert_eq!(lines[4].line_number, 10);
+        // Sub-steps follow their task, numbered after the last task
+        let ids: Vec<_> = lines.iter().map(|l| (l.id, l.parent)).collect();
+        assert_eq!(
+            ids,
+            vec![
+                (1, None),
+                (3, Some(1)),
+                (4, Some(1)),
+                (5, Some(1)),
+                (2, None),
+                (6, Some(2))
+            ]
+        );
+    }
+
+    #[test]
+    fn taskfile_errors_are_reported() {
+        assert!(parse("Taskfile.yml", "tasks: [unclosed").is_err());
+        assert!(steps("Taskfile.yml", "version: '3'\n").is_empty());
+    }
+
+    #[test]
+    fn package_json_scripts() {
+        let content = r#"{
+  "name": "app",
+  "scripts": {
+    "test": "jest",
+    "build": "tsc -p ."
+  }
+}"#;
+        let lines = steps("package.json", content);
+        assert_eq!(contents(&lines), vec!["npm run build", "npm run test"]);
+        assert_eq!(lines[0].description.as_deref(), Some("tsc -p ."));
+        assert_eq!(lines[0].line_number, 5);
+    }
+
+    #[test]
+    fn quotes_are_respected() {
+        assert_eq!(find_unquoted("a ':' b: c", ':'), Some(7));
+        assert_eq!(split_unquoted("a 'b c'  d"), vec!["a", "'b c'", "d"]);
+        assert_eq!(unquote("\"x\""), "x");
+        assert_eq!(unquote("'x"), "'x");
+    }
+}
@@ -0,0 +1,167 @@
+use std::collections::BTreeMap;
+
+const THEME: &str = "theme";
+const COLOR_PREFIX: &str = "color_";
+const NO_COLOR: &str = "none";
+// Zellij themes define four emphasis colors
+const MAX_COLOR: usize = 3;
+
+const PRESET_DEFAULT: &str = "default";
+const PRESET_HIGH_CONTRAST: &str = "high-contrast";
+const PRESET_MONOCHROME: &str = "monochrome";
+
+// An emphasis color of the Zellij theme, from 0 to 3, or None to leave the text plain
+pub(crate) type Color = Option<usize>;
+
+// Colors of the parts of the plugin, from a preset and `color_<part>` overrides
+#[derive(Clone, Debug)]
+pub(crate) struct Theme {
+    // Text of the selected row, on top of the selection background
+    pub selection: Color,
+    // Mode bar, playbook title, panel titles and the recording indicator
+    pub header: Color,
+    pub counter: Color,
+    // Labels of the search block and the prompts
+    pub search: Color,
+    pub cursor: Color,
+    pub hint: Color,
+    pub error: Color,
+    pub info: Color,
+    // Skipped steps
+    pub dimmed: Color,
+    pub tag: Color,
+    // Syntax highlighting of the steps
+    pub command: Color,
+    pub flag: Color,
+    pub string: Color,
+    pub variable: Color,
+    pub operator: Color,
+    pub comment: Color,
+    pub placeholder: Color,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            selection: Some(0),
+            header: Some(3),
+            counter: Some(2),
+            search: Some(2),
+            cursor: Some(3),
+            hint: Some(2),
+            error: Some(0),
+            info: Some(2),
+            // Zellij has no grey, the least prominent emphasis color is used
+            dimmed: Some(1),
+            tag: Some(1),
+            command: Some(2),
+            flag: Some(0),
+            string: Some(1),
+            variable: Some(2),
+            operator: Some(0),
+            comment: Some(1),
+            placeholder: Some(3),
+        }
+    }
+}
+
+impl Theme {
+    // Plain text on the selection, and no part of a step fades away
+    fn high_contrast() -> Self {
+        Self {
+            selection: None,
+            string: Some(3),
+            comment: Some(0),
+            tag: Some(3),
+            ..Self::default()
+        }
+    }
+
+    // Only the selection background and the cursor are kept
+    fn monochrome() -> Self {
+        Self {
+            selection: None,
+            header: None,
+            counter: None,
+            search: None,
+            cursor: Some(0),
+            hint: None,
+            error: None,
+            info: None,
+            dimmed: None,
+            tag: None,
+            command: None,
+            flag: None,
+            string: None,
+            variable: None,
+            operator: None,
+            comment: None,
+            placeholder: None,
+        }
+    }
+
+    pub fn new(conf: BTreeMap<String, String>) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut theme = match conf.get(THEME).map(|t| t.trim()) {
+            None | Some(PRESET_DEFAULT) => Self::default(),
+            Some(PRESET_HIGH_CONTRAST) => Self::high_contrast(),
+            Some(PRESET_MONOCHROME) => Self::monochrome(),
+            Some(other) => {
+                return Err(format!(
+                    "unknown theme '{}', expected '{}', '{}' or '{}'",
+                    other, PRESET_DEFAULT, PRESET_HIGH_CONTRAST, PRESET_MONOCHROME
+                )
+                .into())
+            }
+        };
+
+        for (key, value) in &conf {
+            let Some(part) = key.strip_prefix(COLOR_PREFIX) else {
+                continue;
+            };
+            let color = theme
+                .part_mut(part)
+                .ok_or(format!("unknown color '{}'", key))?;
+            *color = parse_color(value).ok_or(format!(
+                "'{}' must be a number from 0 to {} or '{}', but it's '{}'",
+                key, MAX_COLOR, NO_COLOR, value
+            ))?;
+        }
+        Ok(theme)
+    }
+
+    fn part_mut(&mut self, part: &str) -> Option<&mut Color> {
+        let color = match part {
+            "selection" => &mut self.selection,
+            "header" => &mut self.header,
+            "counter" => &mut self.counter,
+            "search" => &mut self.search,
+            "cursor" => &mut self.cursor,
+            "hint" => &mut self.hint,
+            "error" => &mut self.error,
+            "info" => &mut self.info,
+            "dimmed" => &mut self.dimmed,
+            "tag" => &mut self.tag,
+            "command" => &mut self.command,
+            "flag" => &mut self.flag,
+            "string" => &mut self.string,
+            "variable" => &mut self.variable,
+            "operator" => &mut self.operator,
+            "comment" => &mut self.comment,
+            "placeholder" => &mut self.placeholder,
+            _ => return None,
+        };
+        Some(color)
+    }
+}
+
+fn parse_color(value: &str) -> Option<Color> {
+    let value = value.trim();
+    if value == NO_COLOR {
+        return Some(None);
+    }
+    value
+        .parse::<usize>()
+        .ok()
+        .filter(|c| *c <= MAX_COLOR)
+        .map(Some)
+}
@@ -0,0 +1,134 @@
+use std::collections::BTreeMap;
+
+const OPEN: &str = "{{";
+const CLOSE: &str = "}}";
+
+// A `{{name}}` or `{{name=default}}` placeholder, asked for before the step is sent
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Variable {
+    pub name: String,
+    pub default: Option<String>,
+}
+
+// Placeholders of the content in order of appearance, each name once
+pub(crate) fn find(content: &str) -> Vec<Variable> {
+    let mut variables: Vec<Variable> = Vec::new();
+    for (_, variable) in placeholders(content) {
+        if !variables.iter().any(|v| v.name == variable.name) {
+            variables.push(variable);
+        }
+    }
+    variables
+}
+
+// Replaces the placeholders with their values, or their defaults if they have no value
+pub(crate) fn substitute(content: &str, values: &BTreeMap<String, String>) -> String {
+    let mut result = String::with_capacity(content.len());
+    let mut rest = 0;
+    for (range, variable) in placeholders(content) {
+        result.push_str(&content[rest..range.start]);
+        match values.get(&variable.name).or(variable.default.as_ref()) {
+            Some(value) => result.push_str(value),
+            None => result.push_str(&content[range.clone()]),
+        }
+        rest = range.end;
+    }
+    result.push_str(&content[rest..]);
+    result
+}
+
+// Other `{{ ... }}` texts, such as Go templates, are left alone
+pub(crate) fn placeholders(content: &str) -> Vec<(std::ops::Range<usize>, Variable)> {
+    let mut found = Vec::new();
+    let mut offset = 0;
+    while let Some(begin) = content[offset..].find(OPEN).map(|i| i + offset) {
+        let inner_begin = begin + OPEN.len();
+        let Some(end) = content[inner_begin..].find(CLOSE).map(|i| i + inner_begin) else {
+            break;
+        };
+        let inner = &content[inner_begin..end];
+        let (name, default) = match inner.split_once('=') {
+            Some((name, default)) => (name.trim(), Some(default.to_string())),
+            None => (inner.trim(), None),
+        };
+
+        let valid = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+        if valid {
+            found.push((
+                begin..end + CLOSE.len(),
+                Variable {
+                    name: name.to_string(),
+                    default,
+                },
+            ));
+            offset = end + CLOSE.len();
+        } else {
+            offset = inner_begin;
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variable(name: &str, default: Option<&str>) -> Variable {
+        Variable {
+            name: name.to_string(),
+            default: default.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn find_lists_each_name_once() {
+        assert_eq!(
+            find("scp {{file}} {{host=db}}:{{file}}"),
+            vec![variable("file", None), variable("host", Some("db"))]
+        );
+    }
+
+    #[test]
+    fn find_trims_names_and_keeps_defaults() {
+        assert_eq!(
+            find("echo {{ name = a b }}"),
+            vec![variable("name", Some(" a b "))]
+        );
+        assert_eq!(find("echo {{empty=}}"), vec![variable("empty", Some(""))]);
+    }
+
+    #[test]
+    fn templates_are_not_placeholders() {
+        assert!(find("docker ps --format '{{.Names}}'").is_empty());
+        assert!(find("echo {{}} {{a b}} {{unclosed").is_empty());
+        assert_eq!(find("{{ .Values }} {{name}}"), vec![variable("name", None)]);
+    }
+
+    #[test]
+    fn substitute_uses_values_then_defaults() {
+        let values = BTreeMap::from([("host".to_string(), "web".to_string())]);
+        assert_eq!(
+            substitute("ssh {{host=db}} -p {{port=22}} {{user}}", &values),
+            "ssh web -p 22 {{user}}"
+        );
+    }
+
+    #[test]
+    fn substitute_leaves_other_text_alone() {
+        assert_eq!(
+            substitute("echo {{.Names}} {{é}}", &BTreeMap::new()),
+            "echo {{.Names}} {{é}}"
+        );
+    }
+
+    #[test]
+    fn placeholders_are_byte_ranges() {
+        let content = "é {{a}}";
+        let found = placeholders(content);
+        assert_eq!(found.len(), 1);
+        assert_eq!(&content[found[0].0.clone()], "{{a}}");
+    }
+}
@@ -9,18 +9,16 @@ function! zellij_playbooks#send_text(text)
         return 0
     endif
 
-    " Escape text for shell execution
-    let escaped_text = shellescape(a:text)
-    let command = 'zellij pipe --name zellij-playbooks-pipe -- ' . escaped_text
-    let result = system(command)
-
-    " Check execution result
-    if v:shell_error != 0
-        echoerr 'Failed to send text to Zellij: ' . trim(result)
-        return 0
+    " The text is passed as an argument, without a shell to escape it for
+    let command = ['zellij', 'pipe', '--name', 'zellij-playbooks-pipe', '--', a:text]
+    return s:run(command, function('s:on_sent'))
+endfunction
+
+function! s:on_sent(status, output)
+    if a:status != 0
+        call s:error('Failed to send text to Zellij: ' . a:output)
     else
         echo 'Text sent to Zellij successfully'
-        return 1
     endif
 endfunction
 
@@ -80,6 +78,109 @@ function! zellij_playbooks#send_selected_text()
     return zellij_playbooks#send_text(text)
 endfunction
 
+" Shows the file in the zellij-playbooks plugin and selects the step on the given lines
+function! zellij_playbooks#open_at(line_start, line_end)
+    if !zellij_playbooks#is_zellij_available()
+        echoerr 'Zellij not found. Please install zellij: https://zellij.dev'
+        return 0
+    endif
+
+    let file = expand('%:p')
+    if empty(file)
+        echo 'Current buffer has no file'
+        return 0
+    endif
+
+    " Commas separate the arguments of `zellij pipe`, they can't be escaped
+    if file =~ ','
+        echoerr 'Failed to open in Zellij: the path contains a comma: ' . file
+        return 0
+    endif
+
+    let lines = a:line_start == a:line_end ? a:line_start : a:line_start . '-' . a:line_end
+    let args = 'action=open,file=' . file . ',line=' . lines
+    let command = ['zellij', 'pipe', '--name', 'zellij-playbooks', '--args', args, '--', '']
+    return s:run(command, function('s:on_opened'))
+endfunction
+
+function! s:on_opened(status, output)
+    if a:status != 0 || a:output =~ '"ok":false'
+        call s:error('Failed to open in Zellij: ' . a:output)
+    endif
+endfunction
+
+" Runs a command in the background so that Vim doesn't wait for Zellij,
+" then calls a:callback with the exit status and the output
+function! s:run(command, callback)
+    let job = {'output': [], 'status': -1, 'pending': 2, 'callback': a:callback}
+
+    function! job.collect(lines) dict
+        call extend(self.output, filter(copy(a:lines), '!empty(v:val)'))
+    endfunction
+
+    function! job.exited(status) dict
+        let self.status = a:status
+        call self.done()
+    endfunction
+
+    " Vim reports the exit and the end of the output separately, in any order
+    function! job.done() dict
+        let self.pending -= 1
+        if self.pending == 0
+            call self.callback(self.status, trim(join(self.output, "\n")))
+        endif
+    endfunction
+
+    if has('nvim')
+        " Neovim calls on_exit once the output is read
+        let job.pending = 1
+        let id = jobstart(a:command, {
+            \ 'on_stdout': {id, data, event -> job.collect(data)},
+            \ 'on_stderr': {id, data, event -> job.collect(data)},
+            \ 'on_exit': {id, status, event -> job.exited(status)},
+            \ })
+        if id > 0
+            call chanclose(id, 'stdin')
+        endif
+        let started = id > 0
+    else
+        let handle = job_start(a:command, {
+            \ 'in_io': 'null',
+            \ 'out_cb': {channel, line -> job.collect([line])},
+            \ 'err_cb': {channel, line -> job.collect([line])},
+            \ 'close_cb': {channel -> job.done()},
+            \ 'exit_cb': {handle, status -> job.exited(status)},
+            \ })
+        let started = job_status(handle) !=# 'fail'
+    endif
+
+    if !started
+        echoerr 'Failed to run zellij'
+        return 0
+    endif
+    return 1
+endfunction
+
+" Errors of background jobs can't interrupt the user with echoerr
+function! s:error(message)
+    echohl ErrorMsg
+    echomsg a:message
+    echohl None
+endfunction
+
+" Opens the current line when the cursor rests on another line of the same file
+function! zellij_playbooks#follow_cursor()
+    if empty(expand('%:p')) || &buftype !=# ''
+        return
+    endif
+    let location = expand('%:p') . ':' . line('.')
+    if get(s:, 'last_location', '') ==# location
+        return
+    endif
+    let s:last_location = location
+    silent! call zellij_playbooks#open_at(line('.'), line('.'))
+endfunction
+
 " Displays help message with commands and keybindings
 function! zellij_playbooks#show_help()
     echo "📌 Zellij Playbooks Plugin — Help"
@@ -88,17 +189,22 @@ function! zellij_playbooks#show_help()
     echo "  :ZellijPlaybooks <text>       – Send arbitrary text to Zellij"
     echo "  :ZellijPlaybooksLine          – Send current line"
     echo "  :ZellijPlaybooksWord          – Send word under cursor"
+    echo "  :[range]ZellijPlaybooksOpen   – Show this file in the plugin at the current line"
     echo "  :ZellijPlaybooksHelp          – Show this help"
     echo ""
     echo "⌨️  Key Mappings:"
     echo "  " . get(g:, 'zellij_playbooks_keybinding', '<leader>zp') . "    – Send word under cursor"
     echo "  " . get(g:, 'zellij_playbooks_line_keybinding', '<leader>zl') . "    – Send current line"
     echo "  " . get(g:, 'zellij_playbooks_visual_keybinding', '<leader>zv') . "    – Send selected text (visual mode)"
+    echo "  " . get(g:, 'zellij_playbooks_open_keybinding', '<leader>zo') . "    – Show this file in the plugin at the current line or selection"
     echo ""
     echo "⚙️  Configuration (add to your .vimrc):"
     echo "  let g:zellij_playbooks_keybinding        = '<C-p>'"
     echo "  let g:zellij_playbooks_line_keybinding   = '<C-l>'"
     echo "  let g:zellij_playbooks_visual_keybinding = '<C-v>'"
+    echo "  let g:zellij_playbooks_open_keybinding   = '<C-o>'"
+    echo "  let g:zellij_playbooks_follow_cursor     = 1"
+    echo "  let g:zellij_playbooks_follow_patterns   = '*.playbook,*.md'"
     echo ""
     echo "📦 Requires: zellij (https://zellij.dev)"
 endfunction
@@ -20,15 +20,40 @@ if !exists('g:zellij_playbooks_visual_keybinding')
     let g:zellij_playbooks_visual_keybinding = '<leader>zv'
 endif
 
+if !exists('g:zellij_playbooks_open_keybinding')
+    let g:zellij_playbooks_open_keybinding = '<leader>zo'
+endif
+
+" Follow the cursor in the plugin while editing a playbook
+if !exists('g:zellij_playbooks_follow_cursor')
+    let g:zellij_playbooks_follow_cursor = 0
+endif
+
+" Files whose cursor is followed, as an autocmd pattern
+if !exists('g:zellij_playbooks_follow_patterns')
+    let g:zellij_playbooks_follow_patterns = '*.playbook,*.playbook.*,*.md,Makefile,makefile,GNUmakefile,justfile,Justfile,[Tt]askfile*.yml,[Tt]askfile*.yaml'
+endif
+
 " Define user commands
 command! -nargs=1 ZellijPlaybooks call zellij_playbooks#send_text(<q-args>)
 command! ZellijPlaybooksLine call zellij_playbooks#send_current_line()
 command! ZellijPlaybooksWord call zellij_playbooks#send_current_word()
 command! ZellijPlaybooksHelp call zellij_playbooks#show_help()
+command! -range ZellijPlaybooksOpen call zellij_playbooks#open_at(<line1>, <line2>)
 
 " Normal mode mappings
 execute 'nnoremap <silent> ' . g:zellij_playbooks_keybinding . ' :<C-u>call zellij_playbooks#send_current_word()<CR>'
 execute 'nnoremap <silent> ' . g:zellij_playbooks_line_keybinding . ' :<C-u>call zellij_playbooks#send_current_line()<CR>'
+execute 'nnoremap <silent> ' . g:zellij_playbooks_open_keybinding . ' :<C-u>call zellij_playbooks#open_at(line("."), line("."))<CR>'
 
 " Visual mode mapping
-execute 'xnoremap <silent> ' . g:zellij_playbooks_visual_keybinding . ' :<C-u>call zellij_playbooks#send_selected_text()<CR>'
\ No newline at end of file
+execute 'xnoremap <silent> ' . g:zellij_playbooks_visual_keybinding . ' :<C-u>call zellij_playbooks#send_selected_text()<CR>'
+execute 'xnoremap <silent> ' . g:zellij_playbooks_open_keybinding . ' :<C-u>call zellij_playbooks#open_at(line("''<"), line("''>"))<CR>'
+
+" Cursor following
+if g:zellij_playbooks_follow_cursor
+    augroup zellij_playbooks_follow
+        autocmd!
+        execute 'autocmd CursorHold ' . g:zellij_playbooks_follow_patterns . ' call zellij_playbooks#follow_cursor()'
+    augroup END
+endif